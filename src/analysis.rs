@@ -0,0 +1,315 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use crate::cpu::PROGRAM_START;
+use crate::disasm::disassemble;
+use crate::instruction::Instruction;
+use crate::romdb::{Quirks, RomDatabase, RomInfo};
+
+// Platforms in the order they extend each other, a ROM needs the latest one it has instructions of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Chip8,
+    SuperChip,
+    XoChip
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Platform::Chip8 => "CHIP-8",
+            Platform::SuperChip => "SUPER-CHIP",
+            Platform::XoChip => "XO-CHIP"
+        })
+    }
+}
+
+impl Platform {
+    // Id of the platform in the ROM database.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "originalChip8",
+            Platform::SuperChip => "superchip",
+            Platform::XoChip => "xochip"
+        }
+    }
+}
+
+// Instructions that behave differently depending on a quirk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sensitive {
+    // 8XY1-3
+    Logic,
+    // 8XY6, 8XYE
+    Shift,
+    // FX55, FX65
+    LoadStore,
+    // BNNN
+    Jump,
+    // DXYN
+    Draw,
+    // FX0A
+    KeyWait,
+    // FX1E
+    AddI
+}
+
+impl Sensitive {
+    pub const ALL: [Sensitive; 7] = [
+        Sensitive::Logic, Sensitive::Shift, Sensitive::LoadStore, Sensitive::Jump, Sensitive::Draw, Sensitive::KeyWait, Sensitive::AddI
+    ];
+
+    pub fn of(op: u16) -> Option<Sensitive> {
+        let n = op & 0xF;
+        match op >> 12 {
+            0x8 if (1..=3).contains(&n) => Some(Sensitive::Logic),
+            0x8 if n == 0x6 || n == 0xE => Some(Sensitive::Shift),
+            0xB => Some(Sensitive::Jump),
+            0xD => Some(Sensitive::Draw),
+            0xF => match op & 0xFF {
+                0x55 | 0x65 => Some(Sensitive::LoadStore),
+                0x0A => Some(Sensitive::KeyWait),
+                0x1E => Some(Sensitive::AddI),
+                _ => None
+            },
+            _ => None
+        }
+    }
+
+    // Opcodes and the option for the quirk affecting them.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Sensitive::Logic => "8XY1-3 (--vf-reset)",
+            Sensitive::Shift => "8XY6/8XYE (--shift-x)",
+            Sensitive::LoadStore => "FX55/FX65 (--mem-inc)",
+            Sensitive::Jump => "BNNN (--jump-vx)",
+            Sensitive::Draw => "DXYN (--clipping)",
+            Sensitive::KeyWait => "FX0A (--key-release)",
+            Sensitive::AddI => "FX1E (--i-overflow)"
+        }
+    }
+}
+
+// What could be worked out about a ROM without running it.
+pub struct Analysis {
+    pub platform: Platform,
+    // Address and opcode of each instruction only later platforms have.
+    pub extended: Vec<(u16, u16)>,
+    // Addresses of the quirk-sensitive instructions of each kind used.
+    pub sensitive: Vec<(Sensitive, Vec<u16>)>,
+    // An 8XY6 or 8XYE with X different from Y, the only case where the shift quirk matters.
+    pub shifts_other_register: bool,
+    // I is used after an FX55 or FX65 before being set again, so it matters where they leave it.
+    pub uses_i_after_load_store: bool,
+    // Amount of bytes decoded as instructions.
+    pub code_size: usize
+}
+
+impl Analysis {
+    fn uses(&self, kind: Sensitive) -> bool {
+        self.sensitive.iter().any(|(k, _)| *k == kind)
+    }
+
+    // The quirks of `platform` that the code depends on, the others are left unset.
+    pub fn quirks(&self, platform: Quirks) -> Quirks {
+        let load_store = self.uses_i_after_load_store;
+        Quirks {
+            shift: platform.shift.filter(|_| self.shifts_other_register),
+            memory_increment_by_x: platform.memory_increment_by_x.filter(|_| load_store),
+            memory_leave_i_unchanged: platform.memory_leave_i_unchanged.filter(|_| load_store),
+            wrap: platform.wrap.filter(|_| self.uses(Sensitive::Draw)),
+            jump: platform.jump.filter(|_| self.uses(Sensitive::Jump)),
+            vblank: platform.vblank.filter(|_| self.uses(Sensitive::Draw)),
+            logic: platform.logic.filter(|_| self.uses(Sensitive::Logic))
+        }
+    }
+
+    // Stands in for the database entry of a ROM that has none, with the detected platform's quirks.
+    pub fn rom_info(&self, db: &RomDatabase, title: &str) -> RomInfo {
+        let platform = db.platform(self.platform.id()).cloned();
+        RomInfo {
+            title: title.to_string(),
+            authors: Vec::new(),
+            quirks: platform.as_ref().map(|p| self.quirks(p.quirks)).unwrap_or_default(),
+            platform,
+            tickrate: None,
+            colors: Vec::new(),
+            keys: HashMap::new()
+        }
+    }
+}
+
+// Decodes the code reachable from the start of the program by following jumps, calls and skips.
+// Targets of BNNN depend on V0, so only NNN itself is followed, and code only reached that way or
+// through self-modification is missed. The result is a guess.
+pub fn analyse(rom: &[u8]) -> Analysis {
+    let code = reachable(rom);
+    let mut extended = Vec::new();
+    let mut sensitive: Vec<(Sensitive, Vec<u16>)> = Sensitive::ALL.iter().map(|s| (*s, Vec::new())).collect();
+    let mut shifts_other_register = false;
+    let mut uses_i_after_load_store = false;
+
+    for &addr in &code {
+        let op = fetch(rom, addr).unwrap_or_default();
+        let args = Instruction::new(op);
+        if extension(op).is_some() {
+            extended.push((addr, op));
+        }
+        let Some(kind) = Sensitive::of(op) else { continue };
+        if let Some((_, addrs)) = sensitive.iter_mut().find(|(s, _)| *s == kind) {
+            addrs.push(addr);
+        }
+        match kind {
+            Sensitive::Shift => shifts_other_register |= args.x != args.y,
+            Sensitive::LoadStore => uses_i_after_load_store |= reads_i_after(rom, addr),
+            _ => {}
+        }
+    }
+    sensitive.retain(|(_, addrs)| !addrs.is_empty());
+
+    let platform = extended.iter().filter_map(|(_, op)| extension(*op)).max().unwrap_or(Platform::Chip8);
+    Analysis { platform, extended, sensitive, shifts_other_register, uses_i_after_load_store, code_size: code.len() * 2 }
+}
+
+// Addresses of the instructions reachable from the start of the program, in order.
+fn reachable(rom: &[u8]) -> BTreeSet<u16> {
+    let mut code = BTreeSet::new();
+    let mut pending = vec![PROGRAM_START as u16];
+
+    while let Some(addr) = pending.pop() {
+        let Some(op) = fetch(rom, addr) else { continue };
+        if !code.insert(addr) {
+            continue;
+        }
+        let args = Instruction::new(op);
+        // XO-CHIP's F000 NNNN is twice as long, skips over it skip both words.
+        let next = addr + if op == 0xF000 { 4 } else { 2 };
+        let skip = next + if fetch(rom, next) == Some(0xF000) { 4 } else { 2 };
+
+        match op >> 12 {
+            // RET and SUPER-CHIP's EXIT.
+            0x0 if op == 0x00EE || op == 0x00FD => {}
+            0x1 => pending.push(args.nnn),
+            0x2 => pending.extend([args.nnn, next]),
+            0x3 | 0x4 => pending.extend([next, skip]),
+            0x5 | 0x9 if args.n == 0 => pending.extend([next, skip]),
+            0xB => pending.push(args.nnn),
+            0xE if args.kk == 0x9E || args.kk == 0xA1 => pending.extend([next, skip]),
+            _ if disassemble(op).starts_with("DW") && extension(op).is_none() => {}
+            _ => pending.push(next)
+        }
+    }
+    code
+}
+
+// Follows the code after the FX55 or FX65 at `addr` until I is read or set, giving up at
+// calls, returns and computed jumps.
+fn reads_i_after(rom: &[u8], addr: u16) -> bool {
+    let mut addr = addr + 2;
+    // Enough to get past the register shuffling that usually follows, without looping forever.
+    for _ in 0..32 {
+        let Some(op) = fetch(rom, addr) else { return false };
+        let args = Instruction::new(op);
+        match op >> 12 {
+            // LD I, NNN and XO-CHIP's F000 NNNN, which loads I from the word after it.
+            0xA => return false,
+            0xF if op == 0xF000 => return false,
+            0xD => return true,
+            0xF => match args.kk {
+                0x1E | 0x33 | 0x55 | 0x65 | 0x75 | 0x85 => return true,
+                0x29 | 0x30 => return false,
+                _ => addr += 2
+            },
+            0x0 if op == 0x00EE || op == 0x00FD => return false,
+            0x1 => addr = args.nnn,
+            0x2 | 0xB => return false,
+            _ => addr += 2
+        }
+    }
+    false
+}
+
+fn fetch(rom: &[u8], addr: u16) -> Option<u16> {
+    let offset = (addr as usize).checked_sub(PROGRAM_START)?;
+    let bytes = rom.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+// The platform that introduced `op`, if it isn't part of CHIP-8.
+pub fn extension(op: u16) -> Option<Platform> {
+    let Instruction { n, kk, .. } = Instruction::new(op);
+    match op >> 12 {
+        0x0 if op & 0xFFF0 == 0x00C0 && n > 0 => Some(Platform::SuperChip),
+        0x0 if op & 0xFFF0 == 0x00D0 => Some(Platform::XoChip),
+        0x0 if (0x00FB..=0x00FF).contains(&op) => Some(Platform::SuperChip),
+        0x5 if n == 2 || n == 3 => Some(Platform::XoChip),
+        0xD if n == 0 => Some(Platform::SuperChip),
+        0xF => match kk {
+            0x00 if op == 0xF000 => Some(Platform::XoChip),
+            0x01 | 0x3A => Some(Platform::XoChip),
+            0x02 if op == 0xF002 => Some(Platform::XoChip),
+            0x30 | 0x75 | 0x85 => Some(Platform::SuperChip),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    fn rom(source: &str) -> Vec<u8> {
+        asm::assemble(source).unwrap()
+    }
+
+    #[test]
+    fn reachable_follows_jumps_calls_and_skips() {
+        let rom = rom("
+                    CALL sub        ; 0x200
+                    SE V0, 1        ; 0x202
+                    JP end          ; 0x204
+                    CLS             ; 0x206, only reached by the skip
+            end:    JP end          ; 0x208
+                    DW 0xffff       ; 0x20a, data after the loop
+            sub:    RET             ; 0x20c
+                    DW 0xffff       ; 0x20e, data after the return
+        ");
+        let code: Vec<u16> = reachable(&rom).into_iter().collect();
+        assert_eq!(code, [0x200, 0x202, 0x204, 0x206, 0x208, 0x20c]);
+    }
+
+    #[test]
+    fn skips_step_over_long_instructions() {
+        // F000 NNNN is four bytes, so the skip lands after it.
+        let code = reachable(&rom("SE V0, 1\n DW 0xf000, 0x0300\n end: JP end"));
+        assert_eq!(code.into_iter().collect::<Vec<_>>(), [0x200, 0x202, 0x206]);
+    }
+
+    #[test]
+    fn shifts_other_register() {
+        assert!(!analyse(&rom("SHR V1\n SHL V2, V2")).shifts_other_register);
+        assert!(analyse(&rom("SHR V1, V2")).shifts_other_register);
+    }
+
+    #[test]
+    fn uses_i_after_load_store() {
+        assert!(analyse(&rom("LD [I], V1\n ADD V1, 1\n DRW V0, V0, 1")).uses_i_after_load_store);
+        assert!(analyse(&rom("LD V1, [I]\n JP next\n next: LD V2, [I]")).uses_i_after_load_store);
+        assert!(!analyse(&rom("LD [I], V1\n LD I, 0x300\n DRW V0, V0, 1")).uses_i_after_load_store);
+        assert!(!analyse(&rom("LD [I], V1\n DW 0xf000, 0x0300\n DRW V0, V0, 1")).uses_i_after_load_store);
+        assert!(!analyse(&rom("LD [I], V1\n LD F, V1\n DRW V0, V0, 5")).uses_i_after_load_store);
+    }
+
+    #[test]
+    fn platform_from_extended_instructions() {
+        assert_eq!(analyse(&rom("CLS\n end: JP end")).platform, Platform::Chip8);
+        let analysis = analyse(&rom("DW 0x00ff\n DRW V0, V1, 0\n end: JP end"));
+        assert_eq!(analysis.platform, Platform::SuperChip);
+        assert_eq!(analysis.extended, [(0x200, 0x00FF), (0x202, 0xD010)]);
+        assert_eq!(analyse(&rom("DW 0x00ff, 0xf000, 0x0300\n end: JP end")).platform, Platform::XoChip);
+        // Unreachable data doesn't count.
+        assert_eq!(analyse(&rom("end: JP end\n DW 0x00ff")).platform, Platform::Chip8);
+    }
+}
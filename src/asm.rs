@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+
+// Assembler for the mnemonics printed by `disasm::disassemble` (Cowgod's technical reference),
+// so disassembled code can be edited and assembled again.
+//
+//     ; Comments start with a semicolon.
+//     start:  LD V0, 0x0a
+//             LD I, sprite
+//             DRW V0, V0, 5
+//     loop:   JP loop
+//     sprite: DB 0xf0, 0x90, 0xf0, 0x90, 0xf0
+//
+// Numbers are decimal, 0x hex or 0b binary. Labels can be used wherever an address is expected.
+// DB emits bytes and DW big-endian words, both separated by commas.
+
+// The program is loaded at 0x200, labels resolve to addresses from there.
+pub const ORIGIN: u16 = 0x200;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand<'a> {
+    V(u8),
+    I,
+    // [I]
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    B,
+    Imm(Value<'a>)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value<'a> {
+    Number(u16),
+    Label(&'a str)
+}
+
+struct Line<'a> {
+    number: usize,
+    mnemonic: String,
+    operands: Vec<Operand<'a>>
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    // First pass finds the address of every label, the second encodes with them known.
+    let mut labels: HashMap<&str, u16> = HashMap::new();
+    let mut lines = Vec::new();
+    let mut addr = ORIGIN;
+
+    for (i, text) in source.lines().enumerate() {
+        let number = i + 1;
+        let mut text = text.split(';').next().unwrap_or("").trim();
+
+        while let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_identifier(label) {
+                break;
+            }
+            if labels.insert(label, addr).is_some() {
+                return Err(format!("line {number}: label `{label}` defined twice"));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let operands = if operands.trim().is_empty() {
+            Vec::new()
+        }
+        else {
+            operands.split(',').map(|o| parse_operand(o.trim())).collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("line {number}: {e}"))?
+        };
+        let line = Line { number, mnemonic: mnemonic.to_uppercase(), operands };
+        addr += size(&line) as u16;
+        lines.push(line);
+    }
+
+    let mut rom = Vec::new();
+    for line in &lines {
+        let bytes = encode(line, &labels).map_err(|e| format!("line {}: {e}", line.number))?;
+        rom.extend(bytes);
+    }
+    Ok(rom)
+}
+
+fn size(line: &Line) -> usize {
+    match line.mnemonic.as_str() {
+        "DB" => line.operands.len(),
+        "DW" => line.operands.len() * 2,
+        _ => 2
+    }
+}
+
+fn encode(line: &Line, labels: &HashMap<&str, u16>) -> Result<Vec<u8>, String> {
+    use Operand::*;
+
+    let value = |v: &Value, max: u16| -> Result<u16, String> {
+        let n = match v {
+            Value::Number(n) => *n,
+            Value::Label(name) => *labels.get(name).ok_or_else(|| format!("unknown label `{name}`"))?
+        };
+        if n > max {
+            return Err(format!("0x{n:x} is larger than 0x{max:x}"));
+        }
+        Ok(n)
+    };
+
+    if line.mnemonic == "DB" || line.mnemonic == "DW" {
+        let mut bytes = Vec::new();
+        for operand in &line.operands {
+            let Imm(v) = operand else { return Err("expected a number".to_string()) };
+            if line.mnemonic == "DB" {
+                bytes.push(value(v, 0xFF)? as u8);
+            }
+            else {
+                bytes.extend(value(v, 0xFFFF)?.to_be_bytes());
+            }
+        }
+        return Ok(bytes);
+    }
+
+    let xy = |op: u16, x: u8, y: u8| op | (x as u16) << 8 | (y as u16) << 4;
+    let op = match (line.mnemonic.as_str(), line.operands.as_slice()) {
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("SYS", [Imm(a)]) => value(a, 0xFFF)?,
+        ("JP", [Imm(a)]) => 0x1000 | value(a, 0xFFF)?,
+        ("JP", [V(0), Imm(a)]) => 0xB000 | value(a, 0xFFF)?,
+        ("CALL", [Imm(a)]) => 0x2000 | value(a, 0xFFF)?,
+        ("SE", [V(x), Imm(kk)]) => xy(0x3000, *x, 0) | value(kk, 0xFF)?,
+        ("SNE", [V(x), Imm(kk)]) => xy(0x4000, *x, 0) | value(kk, 0xFF)?,
+        ("SE", [V(x), V(y)]) => xy(0x5000, *x, *y),
+        ("SNE", [V(x), V(y)]) => xy(0x9000, *x, *y),
+        ("LD", [V(x), Imm(kk)]) => xy(0x6000, *x, 0) | value(kk, 0xFF)?,
+        ("ADD", [V(x), Imm(kk)]) => xy(0x7000, *x, 0) | value(kk, 0xFF)?,
+        ("LD", [V(x), V(y)]) => xy(0x8000, *x, *y),
+        ("OR", [V(x), V(y)]) => xy(0x8001, *x, *y),
+        ("AND", [V(x), V(y)]) => xy(0x8002, *x, *y),
+        ("XOR", [V(x), V(y)]) => xy(0x8003, *x, *y),
+        ("ADD", [V(x), V(y)]) => xy(0x8004, *x, *y),
+        ("SUB", [V(x), V(y)]) => xy(0x8005, *x, *y),
+        ("SHR", [V(x), V(y)]) => xy(0x8006, *x, *y),
+        ("SHR", [V(x)]) => xy(0x8006, *x, *x),
+        ("SUBN", [V(x), V(y)]) => xy(0x8007, *x, *y),
+        ("SHL", [V(x), V(y)]) => xy(0x800E, *x, *y),
+        ("SHL", [V(x)]) => xy(0x800E, *x, *x),
+        ("LD", [I, Imm(a)]) => 0xA000 | value(a, 0xFFF)?,
+        ("RND", [V(x), Imm(kk)]) => xy(0xC000, *x, 0) | value(kk, 0xFF)?,
+        ("DRW", [V(x), V(y), Imm(n)]) => xy(0xD000, *x, *y) | value(n, 0xF)?,
+        ("SKP", [V(x)]) => xy(0xE09E, *x, 0),
+        ("SKNP", [V(x)]) => xy(0xE0A1, *x, 0),
+        ("LD", [V(x), Dt]) => xy(0xF007, *x, 0),
+        ("LD", [V(x), K]) => xy(0xF00A, *x, 0),
+        ("LD", [Dt, V(x)]) => xy(0xF015, *x, 0),
+        ("LD", [St, V(x)]) => xy(0xF018, *x, 0),
+        ("ADD", [I, V(x)]) => xy(0xF01E, *x, 0),
+        ("LD", [F, V(x)]) => xy(0xF029, *x, 0),
+        ("LD", [B, V(x)]) => xy(0xF033, *x, 0),
+        ("LD", [IndirectI, V(x)]) => xy(0xF055, *x, 0),
+        ("LD", [V(x), IndirectI]) => xy(0xF065, *x, 0),
+        (mnemonic, operands) => {
+            return Err(format!("no instruction `{mnemonic}` taking {} operand(s) of these kinds", operands.len()));
+        }
+    };
+    Ok(op.to_be_bytes().to_vec())
+}
+
+fn parse_operand(s: &str) -> Result<Operand<'_>, String> {
+    let upper = s.to_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "B" => Operand::B,
+        _ if upper.len() == 2 && upper.starts_with('V') => {
+            let x = u8::from_str_radix(&upper[1..], 16).map_err(|_| format!("`{s}` is not a register"))?;
+            Operand::V(x)
+        }
+        _ if is_identifier(s) => Operand::Imm(Value::Label(s)),
+        _ => Operand::Imm(Value::Number(parse_number(s)?))
+    };
+    Ok(operand)
+}
+
+fn parse_number(s: &str) -> Result<u16, String> {
+    let lower = s.to_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16)
+    }
+    else if let Some(bin) = lower.strip_prefix("0b") {
+        u16::from_str_radix(bin, 2)
+    }
+    else {
+        lower.parse()
+    };
+    parsed.map_err(|_| format!("`{s}` is not a number, register or label"))
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+
+    #[test]
+    fn assembles_every_mnemonic() {
+        let cases = [
+            ("CLS", 0x00E0),
+            ("RET", 0x00EE),
+            ("SYS 0x123", 0x0123),
+            ("JP 0x234", 0x1234),
+            ("JP V0, 0x234", 0xB234),
+            ("CALL 0x345", 0x2345),
+            ("SE V1, 0x22", 0x3122),
+            ("SNE V1, 0x22", 0x4122),
+            ("SE V1, V2", 0x5120),
+            ("SNE V1, V2", 0x9120),
+            ("LD V1, 0x22", 0x6122),
+            ("ADD V1, 0x22", 0x7122),
+            ("LD V1, V2", 0x8120),
+            ("OR V1, V2", 0x8121),
+            ("AND V1, V2", 0x8122),
+            ("XOR V1, V2", 0x8123),
+            ("ADD V1, V2", 0x8124),
+            ("SUB V1, V2", 0x8125),
+            ("SHR V1, V2", 0x8126),
+            ("SHR V1", 0x8116),
+            ("SUBN V1, V2", 0x8127),
+            ("SHL V1, V2", 0x812E),
+            ("SHL V1", 0x811E),
+            ("LD I, 0x456", 0xA456),
+            ("RND V1, 0x22", 0xC122),
+            ("DRW V1, V2, 5", 0xD125),
+            ("SKP V1", 0xE19E),
+            ("SKNP V1", 0xE1A1),
+            ("LD V1, DT", 0xF107),
+            ("LD V1, K", 0xF10A),
+            ("LD DT, V1", 0xF115),
+            ("LD ST, V1", 0xF118),
+            ("ADD I, V1", 0xF11E),
+            ("LD F, V1", 0xF129),
+            ("LD B, V1", 0xF133),
+            ("LD [I], V1", 0xF155),
+            ("LD V1, [I]", 0xF165)
+        ];
+        for (source, op) in cases {
+            assert_eq!(assemble(source), Ok(u16::to_be_bytes(op).to_vec()), "{source}");
+        }
+    }
+
+    #[test]
+    fn data_and_labels() {
+        let source = "start: JP end ; comment\n DB 1, 0x2, 0b11\n DW 0xabcd\nend: CALL start";
+        assert_eq!(assemble(source), Ok(vec![0x12, 0x07, 1, 2, 3, 0xAB, 0xCD, 0x22, 0x00]));
+        assert!(assemble("JP nowhere").unwrap_err().contains("line 1"));
+        assert!(assemble("LD V1, 0x100").is_err());
+    }
+
+    #[test]
+    fn disassembly_assembles_to_the_same_bytes() {
+        for op in 0..=u16::MAX {
+            let text = disassemble(op);
+            assert_eq!(assemble(&text), Ok(op.to_be_bytes().to_vec()), "{op:04x} disassembled as {text}");
+        }
+    }
+}
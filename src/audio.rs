@@ -0,0 +1,140 @@
+use std::f32::consts::TAU;
+use std::io;
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sdl2::Sdl;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+
+use crate::wav::WavWriter;
+
+pub const SAMPLE_RATE: u32 = 44100;
+// Timers tick at 60Hz, audio is generated one timer frame at a time.
+pub const TIMER_HZ: u32 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AudioSettings {
+    pub frequency: f32,
+    // 0.0 to 1.0
+    pub volume: f32,
+    pub waveform: Waveform
+}
+
+// Produces the beeper signal for each 60Hz timer frame.
+// Output depends only on the sequence of frames, never on wall clock time.
+pub struct ToneGenerator {
+    settings: AudioSettings,
+    sample_rate: u32,
+    // Position within the current wave period, 0.0 to 1.0.
+    phase: f32,
+    // Fractional samples carried over so frames average exactly sample_rate / 60 samples.
+    remainder: u32,
+    playing: bool
+}
+
+impl ToneGenerator {
+    pub fn new(settings: AudioSettings, sample_rate: u32) -> Self {
+        ToneGenerator { settings, sample_rate, phase: 0.0, remainder: 0, playing: false }
+    }
+
+    // Samples for one timer frame, a tone if `on` (i.e. ST > 0) or silence otherwise.
+    pub fn frame(&mut self, on: bool) -> Vec<f32> {
+        let total = self.sample_rate + self.remainder;
+        let count = total / TIMER_HZ;
+        self.remainder = total % TIMER_HZ;
+
+        // Start every beep at the beginning of a period.
+        if on && !self.playing {
+            self.phase = 0.0;
+        }
+        self.playing = on;
+
+        if !on {
+            return vec![0.0; count as usize];
+        }
+
+        let step = self.settings.frequency / self.sample_rate as f32;
+        (0..count).map(|_| {
+            let sample = self.sample();
+            self.phase = (self.phase + step).fract();
+            sample * self.settings.volume
+        }).collect()
+    }
+
+    fn sample(&self) -> f32 {
+        let p = self.phase;
+        match self.settings.waveform {
+            Waveform::Square => if p < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Sine => (p * TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (p - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * p - 1.0
+        }
+    }
+}
+
+pub struct Beeper {
+    queue: AudioQueue<f32>,
+    generator: ToneGenerator
+}
+
+impl Beeper {
+    pub fn new(sdl: &Sdl, settings: AudioSettings) -> Result<Self, String> {
+        let audio = sdl.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: Some(512)
+        };
+        let queue: AudioQueue<f32> = audio.open_queue(None, &desired)?;
+        let generator = ToneGenerator::new(settings, queue.spec().freq as u32);
+        queue.resume();
+
+        Ok(Beeper { queue, generator })
+    }
+
+    // Queues one timer frame of audio.
+    pub fn frame(&mut self, on: bool) {
+        let samples = self.generator.frame(on);
+
+        // Drop frames rather than build up latency if emulation runs ahead of playback.
+        let queued = self.queue.size() as usize / std::mem::size_of::<f32>();
+        if queued > samples.len() * 4 {
+            return;
+        }
+        let _ = self.queue.queue_audio(&samples);
+    }
+}
+
+// Records the beeper to a WAV file, generated from emulated time so it also works headless.
+pub struct AudioRecorder {
+    writer: WavWriter,
+    generator: ToneGenerator
+}
+
+impl AudioRecorder {
+    pub fn create(path: &Path, settings: AudioSettings) -> io::Result<Self> {
+        Ok(AudioRecorder {
+            writer: WavWriter::create(path, SAMPLE_RATE)?,
+            generator: ToneGenerator::new(settings, SAMPLE_RATE)
+        })
+    }
+
+    // Records one timer frame of audio.
+    pub fn frame(&mut self, on: bool) -> io::Result<()> {
+        self.writer.write(&self.generator.frame(on))
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.writer.finish()
+    }
+}
@@ -0,0 +1,164 @@
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+use crate::analysis;
+use crate::asm;
+use crate::audio::TIMER_HZ;
+use crate::config::{self, QuirkConfig};
+use crate::cpu::{CPU, MAX_ROM_SIZE, PROGRAM_START};
+use crate::disasm;
+use crate::display::{DISPLAY_SIZE, DISPLAY_WIDTH};
+use crate::emulator::Emulator;
+use crate::quirk::QuirkArgs;
+use crate::romdb::{self, RomDatabase};
+
+// Subcommands other than `run`, which lives in main with the frontends.
+
+// Listed as assembler source with the address and opcode in a comment, so the output can be edited and assembled again.
+pub fn disasm(rom_path: &Path, start: u16, count: Option<usize>) {
+    let rom = read_rom(rom_path);
+    let memory = memory_image(&rom);
+
+    let end = PROGRAM_START + rom.len().min(MAX_ROM_SIZE);
+    let count = count.unwrap_or(end.saturating_sub(start as usize).div_ceil(2));
+    for (addr, op, text) in disasm::disassemble_range(&memory, start, count) {
+        println!("{text:<20}; 0x{addr:03x}  {op:04x}");
+    }
+}
+
+pub fn assemble(source: &Path, output: Option<&Path>) {
+    let text = fs::read_to_string(source).unwrap_or_else(|_| panic!("Failed to read file at: {}", source.display()));
+    let rom = asm::assemble(&text).unwrap_or_else(|e| {
+        println!("{}: {e}", source.display());
+        process::exit(1);
+    });
+
+    let output = output.map_or_else(|| source.with_extension("ch8"), Path::to_path_buf);
+    fs::write(&output, &rom).unwrap_or_else(|e| panic!("Failed to write {}: {e}", output.display()));
+    println!("Assembled {} bytes to {}", rom.len(), output.display());
+}
+
+// Only the built-in defaults and the given quirks apply, so results don't depend on the user's config.
+pub fn test(rom_path: &Path, frames: u64, ticks_per_frame: u8, expect: Option<&str>, quirks: QuirkArgs) {
+    let mut emu = headless_emulator(rom_path, ticks_per_frame, quirks);
+    crate::run_headless(&mut emu, frames);
+
+    for row in emu.chip.vbuffer.chunks(DISPLAY_WIDTH) {
+        println!("{}", row.iter().map(|p| if *p { '#' } else { '.' }).collect::<String>());
+    }
+    let hash = framebuffer_hash(&emu.chip.vbuffer);
+    println!("Framebuffer: {hash}");
+
+    if let Some(expect) = expect {
+        if !expect.eq_ignore_ascii_case(&hash) {
+            println!("Expected {expect}");
+            process::exit(1);
+        }
+        println!("OK");
+    }
+}
+
+// Amount of instructions from the start of the program shown by `info`.
+const ENTRY_INSTRUCTIONS: usize = 8;
+
+pub fn info(rom_path: &Path, rom_db: Option<&Path>) {
+    let rom = read_rom(rom_path);
+    let hash = romdb::sha1(&rom);
+    if rom.len() > MAX_ROM_SIZE {
+        println!("Size: {} bytes, too large to load, at most {MAX_ROM_SIZE} fit", rom.len());
+    }
+    else {
+        println!("Size: {} bytes", rom.len());
+    }
+    println!("SHA-1: {hash}");
+
+    let db_dir = rom_db.map(Path::to_path_buf).or_else(romdb::user_dir);
+    let db = RomDatabase::load(db_dir.as_deref()).unwrap_or_else(|e| panic!("Failed to load ROM database: {e}"));
+    let known = db.lookup(&hash);
+    match &known {
+        Some(info) => crate::print_rom_info(info),
+        None => println!("Not in the database")
+    }
+
+    let analysis = analysis::analyse(&rom);
+    let evidence: Vec<String> = analysis.extended.iter().take(4).map(|(addr, op)| format!("{op:04x} at 0x{addr:03x}")).collect();
+    if evidence.is_empty() {
+        println!("Detected platform: {}", analysis.platform);
+    }
+    else {
+        let more = if analysis.extended.len() > evidence.len() { ", ..." } else { "" };
+        println!("Detected platform: {} ({}{more})", analysis.platform, evidence.join(", "));
+    }
+
+    if known.is_none() {
+        let quirks = config::quirk_settings(analysis.rom_info(&db, "").quirks);
+        let quirks: Vec<String> = quirks.iter().map(|(name, value)| format!("{name}={value}")).collect();
+        println!("Proposed quirks: {}", if quirks.is_empty() { "defaults".to_string() } else { quirks.join(", ") });
+    }
+    println!("Reachable code: {} of {} bytes", analysis.code_size, rom.len());
+    println!("Entry code:");
+    let memory = memory_image(&rom);
+    for (addr, op, text) in disasm::disassemble_range(&memory, PROGRAM_START as u16, ENTRY_INSTRUCTIONS.min(rom.len().div_ceil(2))) {
+        println!("  0x{addr:03x}  {op:04x}  {text}");
+    }
+
+    if analysis.sensitive.is_empty() {
+        println!("Quirk-sensitive instructions: none");
+    }
+    else {
+        println!("Quirk-sensitive instructions:");
+        for (kind, addrs) in &analysis.sensitive {
+            let shown: Vec<String> = addrs.iter().take(6).map(|a| format!("0x{a:03x}")).collect();
+            let more = if addrs.len() > shown.len() { ", ..." } else { "" };
+            println!("  {:<22} {} at {}{more}", kind.describe(), addrs.len(), shown.join(", "));
+        }
+    }
+}
+
+pub fn bench(rom_path: &Path, frames: u64, ticks_per_frame: u8, quirks: QuirkArgs) {
+    let mut emu = headless_emulator(rom_path, ticks_per_frame, quirks);
+
+    let start = Instant::now();
+    let mut ran = 0;
+    while ran < frames && emu.run_frame() {
+        ran += 1;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let instructions = ran * ticks_per_frame as u64;
+    let rate = instructions as f64 / elapsed.max(f64::EPSILON);
+    let realtime = ticks_per_frame as f64 * TIMER_HZ as f64;
+    println!("{instructions} instructions in {elapsed:.3}s");
+    println!("{rate:.0} instructions/s, {:.1}x real time", rate / realtime);
+}
+
+fn headless_emulator(rom_path: &Path, ticks_per_frame: u8, quirks: QuirkArgs) -> Emulator {
+    let mut config = QuirkConfig::default();
+    quirks.apply(&mut config);
+    let mut chip = CPU::new(config.quirk());
+    chip.load_rom(read_rom(rom_path)).unwrap_or_else(|e| {
+        eprintln!("Failed to load {}: {e}", rom_path.display());
+        process::exit(1);
+    });
+    Emulator::new(chip, ticks_per_frame, 0)
+}
+
+// Memory with the ROM at the start of the program, cut off if it doesn't fit.
+fn memory_image(rom: &[u8]) -> [u8; 0x1000] {
+    let mut memory = [0u8; 0x1000];
+    let len = rom.len().min(MAX_ROM_SIZE);
+    memory[PROGRAM_START..PROGRAM_START + len].copy_from_slice(&rom[..len]);
+    memory
+}
+
+fn read_rom(path: &Path) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|_| panic!("Failed to read file at: {}", path.display()))
+}
+
+// SHA-1 of the framebuffer with one byte per pixel, 0 or 1.
+fn framebuffer_hash(pixels: &[bool; DISPLAY_SIZE]) -> String {
+    let bytes: Vec<u8> = pixels.iter().map(|p| *p as u8).collect();
+    romdb::sha1(&bytes)
+}
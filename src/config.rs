@@ -0,0 +1,257 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::audio::Waveform;
+use crate::filter::Filter;
+use crate::input::KeymapFile;
+use crate::palette;
+use crate::persistence::PersistenceMode;
+use crate::quirk::Quirk;
+use crate::romdb::{Quirks, RomInfo};
+
+// Settings that persist between runs. Layered from lowest to highest precedence: the defaults
+// below, the user's config.toml, the ROM database entry, the ROM's own file under roms/ and
+// finally the command line. Tables are merged key by key, so a layer only needs the keys it changes.
+//
+//     frontend = "window"
+//     [speed]
+//     ticks_per_frame = 15
+//     [quirks]
+//     vf_reset = true
+//     [display]
+//     palette = "amber"
+//     [audio]
+//     volume = 0.1
+//     [input]
+//     preset = "azerty"
+//     keys = { 5 = ["Z", "Up"] }
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub frontend: Frontend,
+    // Amount of 60Hz frames to run for with the headless frontend.
+    pub frames: u64,
+    pub speed: SpeedConfig,
+    pub quirks: QuirkConfig,
+    pub display: DisplayConfig,
+    pub audio: AudioConfig,
+    // Same format as a keymap file. The effective bindings are printed instead of this.
+    #[serde(skip_serializing)]
+    pub input: KeymapFile
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Frontend {
+    /// SDL window with audio and input.
+    Window,
+    /// No window or audio device, runs for a fixed amount of frames.
+    Headless
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeedConfig {
+    pub ticks_per_frame: u8,
+    pub tick_delay: u64
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuirkConfig {
+    pub vf_reset: bool,
+    pub mem_inc: bool,
+    pub mem_inc_x: bool,
+    pub clipping: bool,
+    pub shift_x: bool,
+    pub jump_vx: bool,
+    pub key_release: bool,
+    pub i_overflow: bool
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    pub scale: usize,
+    // Theme name or hex colours, as taken by --palette.
+    pub palette: String,
+    pub fullscreen: bool,
+    pub integer_scaling: bool,
+    pub persistence: PersistenceMode,
+    // Defaults depend on the persistence mode.
+    pub persistence_strength: Option<f32>,
+    pub filter: Vec<Filter>
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    pub mute: bool,
+    pub frequency: f32,
+    pub volume: f32,
+    pub waveform: Waveform
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            frontend: Frontend::Window,
+            frames: 600,
+            speed: SpeedConfig::default(),
+            quirks: QuirkConfig::default(),
+            display: DisplayConfig::default(),
+            audio: AudioConfig::default(),
+            input: KeymapFile::default()
+        }
+    }
+}
+
+impl Default for SpeedConfig {
+    fn default() -> Self {
+        SpeedConfig { ticks_per_frame: 30, tick_delay: 0 }
+    }
+}
+
+impl Default for QuirkConfig {
+    fn default() -> Self {
+        QuirkConfig { vf_reset: true, mem_inc: true, mem_inc_x: false, clipping: false, shift_x: false, jump_vx: false, key_release: true, i_overflow: false }
+    }
+}
+
+impl QuirkConfig {
+    pub fn quirk(&self) -> Quirk {
+        Quirk {
+            vf_reset: self.vf_reset,
+            mem_inc: self.mem_inc,
+            mem_inc_x: self.mem_inc_x,
+            display_wait: false,
+            // display_wait: cli.display_wait.unwrap_or(false),
+            clipping: self.clipping,
+            shift_x: self.shift_x,
+            jump_vx: self.jump_vx,
+            key_release: self.key_release,
+            i_overflow: self.i_overflow
+        }
+    }
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            scale: 10,
+            palette: "classic".to_string(),
+            fullscreen: false,
+            integer_scaling: false,
+            persistence: PersistenceMode::Off,
+            persistence_strength: None,
+            filter: Vec::new()
+        }
+    }
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig { mute: false, frequency: 440.0, volume: 0.25, waveform: Waveform::Square }
+    }
+}
+
+impl Config {
+    // Merges the config files and the database entry for `rom_path`, `user` replaces the default config.toml.
+    pub fn load(user: Option<&Path>, rom_path: &Path, rom: Option<&RomInfo>) -> Result<Self, String> {
+        let mut table = Table::new();
+        if let Some(path) = user.map(Path::to_path_buf).or_else(|| dir().map(|d| d.join("config.toml"))) {
+            // Only an explicitly given config has to exist.
+            if user.is_some() || path.exists() {
+                merge(&mut table, read(&path)?);
+            }
+        }
+        if let Some(rom) = rom {
+            merge(&mut table, rom_layer(rom));
+        }
+        let name = rom_name(rom_path);
+        let rom_file = dir().filter(|_| !name.is_empty()).map(|d| d.join("roms").join(format!("{name}.toml")));
+        if let Some(path) = rom_file.filter(|p| p.exists()) {
+            merge(&mut table, read(&path)?);
+        }
+
+        let config: Config = Value::Table(table).try_into().map_err(|e| format!("Invalid config: {e}"))?;
+        // The CLI checks its own values, this catches ones from the files.
+        if config.speed.ticks_per_frame == 0 {
+            return Err("Invalid config: speed.ticks_per_frame must be at least 1".to_string());
+        }
+        Ok(config)
+    }
+
+    // The settings in config file syntax, without the key bindings.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).unwrap_or_else(|e| format!("# Failed to print config: {e}\n"))
+    }
+}
+
+// Name a ROM's own settings go by, in roms/<name>.toml and in keymap files: its file name
+// without the extension, so brix.ch8 is `brix`.
+pub fn rom_name(rom_path: &Path) -> String {
+    rom_path.file_stem().map_or(String::new(), |n| n.to_string_lossy().to_string())
+}
+
+// Directory holding config.toml, the per-ROM configs under roms/ and the user's ROM database.
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("chip-8"))
+}
+
+fn read(path: &Path) -> Result<Table, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {e}", path.display()))?;
+    text.parse().map_err(|e| format!("Invalid config {}: {e}", path.display()))
+}
+
+// Keys in `layer` replace those in `base`, tables present in both are merged.
+fn merge(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => merge(base, layer),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+// The quirks set in `q` that are emulated, as names and values of `[quirks]` settings.
+pub fn quirk_settings(q: Quirks) -> Vec<(&'static str, bool)> {
+    let known = [
+        ("vf_reset", q.logic),
+        ("mem_inc", q.memory_leave_i_unchanged.map(|unchanged| !unchanged)),
+        ("mem_inc_x", q.memory_increment_by_x),
+        ("clipping", q.wrap.map(|wrap| !wrap)),
+        ("shift_x", q.shift),
+        ("jump_vx", q.jump)
+    ];
+    known.into_iter().filter_map(|(name, value)| Some((name, value?))).collect()
+}
+
+// The settings a database entry knows about, as a config layer.
+fn rom_layer(rom: &RomInfo) -> Table {
+    let mut quirks = Table::new();
+    for (name, value) in quirk_settings(rom.quirks) {
+        quirks.insert(name.to_string(), Value::Boolean(value));
+    }
+
+    let mut layer = Table::new();
+    layer.insert("quirks".to_string(), Value::Table(quirks));
+    if let Some(tickrate) = rom.tickrate {
+        let mut speed = Table::new();
+        speed.insert("ticks_per_frame".to_string(), Value::Integer(tickrate.clamp(1, u8::MAX as u32) as i64));
+        layer.insert("speed".to_string(), Value::Table(speed));
+    }
+    let palette = rom.colors.join(",");
+    if !rom.colors.is_empty() && palette::parse_palette(&palette).is_ok() {
+        let mut display = Table::new();
+        display.insert("palette".to_string(), Value::String(palette));
+        layer.insert("display".to_string(), Value::Table(display));
+    }
+    layer
+}
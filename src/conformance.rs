@@ -0,0 +1,182 @@
+// Boots test ROMs headless with the quirks they expect, runs them for a fixed amount of frames
+// and compares the screen with a golden image in tests/golden, stored as text like the `test`
+// subcommand prints it.
+//
+// ROMs with a .s source in tests/roms are assembled on the fly, those cover the flags, quirks,
+// keypad and sound. The ROMs of Timendus' chip8-test-suite (https://github.com/Timendus/chip8-test-suite)
+// are expected in tests/roms under their names in that repository. They aren't included, so those
+// cases are ignored by default, run them with `cargo test -- --ignored` once they are in place.
+//
+// Set CHIP8_BLESS=1 to write the current screens as the golden images, after checking they are right.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::asm;
+use crate::config::QuirkConfig;
+use crate::cpu::CPU;
+use crate::display::{DISPLAY_SIZE, DISPLAY_WIDTH};
+use crate::emulator::Emulator;
+use crate::quirk::Quirk;
+
+struct Case {
+    name: &'static str,
+    // Name of the golden image, the ROM's unless it is run several ways.
+    golden: &'static str,
+    quirks: Quirk,
+    frames: u64,
+    ticks_per_frame: u8,
+    // Bytes written to memory after loading, the test suite reads menu choices from 0x1FF.
+    poke: &'static [(u16, u8)],
+    // Key held from the first frame up to, not including, the second.
+    keys: &'static [(u8, u64, u64)]
+}
+
+impl Case {
+    fn new(name: &'static str, frames: u64) -> Self {
+        Case { name, golden: name, quirks: chip8(), frames, ticks_per_frame: 30, poke: &[], keys: &[] }
+    }
+}
+
+// Outcome of a run, the screen and whether the sound timer was ever set.
+struct Run {
+    screen: String,
+    beeped: bool
+}
+
+fn chip8() -> Quirk {
+    QuirkConfig::default().quirk()
+}
+
+fn fixture(dir: &str, file: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join(dir).join(file)
+}
+
+fn load(name: &str) -> Vec<u8> {
+    let source = fixture("roms", &format!("{name}.s"));
+    if let Ok(text) = fs::read_to_string(&source) {
+        return asm::assemble(&text).unwrap_or_else(|e| panic!("{}: {e}", source.display()));
+    }
+    let rom = fixture("roms", &format!("{name}.ch8"));
+    fs::read(&rom).unwrap_or_else(|e| panic!("Missing test ROM {}: {e}", rom.display()))
+}
+
+fn run_rom(case: &Case) -> Run {
+    let mut chip = CPU::new(case.quirks);
+    chip.load_rom(load(case.name)).unwrap();
+    for (addr, value) in case.poke {
+        chip.memory[*addr as usize] = *value;
+    }
+
+    let mut emu = Emulator::new(chip, case.ticks_per_frame, 0);
+    let mut beeped = false;
+    for frame in 0..case.frames {
+        let mut keys = [false; 16];
+        for (key, from, to) in case.keys {
+            keys[*key as usize] |= (*from..*to).contains(&frame);
+        }
+        emu.chip.keypad.update(keys);
+        assert!(emu.run_frame(), "{} stopped at frame {frame}", case.name);
+        beeped |= emu.chip.st() > 0;
+    }
+    Run { screen: screen(&emu.chip.vbuffer), beeped }
+}
+
+fn screen(pixels: &[bool; DISPLAY_SIZE]) -> String {
+    pixels.chunks(DISPLAY_WIDTH).map(|row| row.iter().map(|p| if *p { '#' } else { '.' }).collect::<String>() + "\n").collect()
+}
+
+fn check(case: &Case) -> Run {
+    let result = run_rom(case);
+    let golden = fixture("golden", &format!("{}.txt", case.golden));
+    if env::var_os("CHIP8_BLESS").is_some() {
+        fs::write(&golden, &result.screen).unwrap();
+        return result;
+    }
+    let expected = fs::read_to_string(&golden)
+        .unwrap_or_else(|e| panic!("Missing golden image {}, write it with CHIP8_BLESS=1: {e}", golden.display()));
+    assert!(result.screen == expected, "{} doesn't match {}\nexpected:\n{expected}\ngot:\n{}", case.name, golden.display(), result.screen);
+    result
+}
+
+#[test]
+fn font() {
+    check(&Case::new("font", 10));
+}
+
+#[test]
+fn flags() {
+    check(&Case::new("flags", 10));
+}
+
+#[test]
+fn quirk_behaviour() {
+    check(&Case::new("quirks", 10));
+}
+
+#[test]
+fn quirk_behaviour_flipped() {
+    // Every quirk the other way from the defaults, so each digit changes.
+    let quirks = Quirk { vf_reset: false, mem_inc: false, clipping: true, shift_x: true, jump_vx: true, i_overflow: true, ..chip8() };
+    check(&Case { golden: "quirks-flipped", quirks, ..Case::new("quirks", 10) });
+}
+
+#[test]
+fn key_wait() {
+    // Each key shows up once released, a one frame tap counts too.
+    check(&Case { keys: &[(0x5, 10, 20), (0xA, 30, 31), (0xA, 40, 50)], ..Case::new("keypad", 60) });
+}
+
+#[test]
+fn sound_timer() {
+    let run = check(&Case { keys: &[(0xB, 10, 20)], ..Case::new("beep", 30) });
+    assert!(run.beeped, "beep didn't set the sound timer");
+    assert!(!run_rom(&Case::new("beep", 30)).beeped, "beep set the sound timer without B held");
+}
+
+#[test]
+#[ignore = "needs chip8-test-suite ROMs in tests/roms"]
+fn chip8_logo() {
+    check(&Case::new("1-chip8-logo", 60));
+}
+
+#[test]
+#[ignore = "needs chip8-test-suite ROMs in tests/roms"]
+fn ibm_logo() {
+    check(&Case::new("2-ibm-logo", 60));
+}
+
+#[test]
+#[ignore = "needs chip8-test-suite ROMs in tests/roms"]
+fn corax_plus() {
+    check(&Case::new("3-corax+", 120));
+}
+
+#[test]
+#[ignore = "needs chip8-test-suite ROMs in tests/roms"]
+fn flags_suite() {
+    check(&Case::new("4-flags", 120));
+}
+
+#[test]
+#[ignore = "needs chip8-test-suite ROMs in tests/roms"]
+fn quirks() {
+    // 1 picks CHIP-8 from the menu.
+    check(&Case { poke: &[(0x1FF, 1)], ..Case::new("5-quirks", 600) });
+}
+
+#[test]
+#[ignore = "needs chip8-test-suite ROMs in tests/roms"]
+fn keypad_wait() {
+    // 3 picks the FX0A test, which passes once a key is pressed and released.
+    check(&Case { poke: &[(0x1FF, 3)], keys: &[(0x5, 30, 40)], ..Case::new("6-keypad", 90) });
+}
+
+#[test]
+#[ignore = "needs chip8-test-suite ROMs in tests/roms"]
+fn beep() {
+    // The ROM beeps while B is held.
+    let run = check(&Case { keys: &[(0xB, 30, 60)], ..Case::new("7-beep", 90) });
+    assert!(run.beeped, "7-beep didn't set the sound timer");
+}
@@ -1,8 +1,15 @@
 use crate::display::{DISPLAY_WIDTH, DISPLAY_HEIGHT, DISPLAY_SIZE};
 use crate::instruction::Instruction;
+use crate::keypad::Keypad;
 use crate::quirk::Quirk;
+use crate::watch::{Target, Watchpoint, WatchHit};
 use rand::random;
+use std::cell::Cell;
+use std::fmt;
 
+// Programs are loaded at 0x200 and can fill the rest of the 4K of memory.
+pub const PROGRAM_START: usize = 0x200;
+pub const MAX_ROM_SIZE: usize = 0x1000 - PROGRAM_START;
 
 pub const FONT_SET: [u8; 16 * 5] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0,   // 0
@@ -23,6 +30,27 @@ pub const FONT_SET: [u8; 16 * 5] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80    // F
 ];
 
+// Conditions that stop execution, reported by `CPU::tick` instead of panicking.
+#[derive(Clone, Copy, Debug)]
+pub enum Fault {
+    InvalidOpcode { pc: u16, op: u16 },
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    // PC left no room for a whole instruction in memory.
+    PcOutOfRange { pc: u16 }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::InvalidOpcode { pc, op } => write!(f, "Invalid op 0x{op:04x} at 0x{pc:03x}"),
+            Fault::StackOverflow { pc } => write!(f, "Stack overflow at 0x{pc:03x}"),
+            Fault::StackUnderflow { pc } => write!(f, "Stack underflow at 0x{pc:03x}"),
+            Fault::PcOutOfRange { pc } => write!(f, "PC out of memory at 0x{pc:03x}")
+        }
+    }
+}
+
 #[allow(non_snake_case)]
 pub struct CPU {
     pub memory: [u8; 0x1000],
@@ -45,65 +73,77 @@ pub struct CPU {
     pub vbuffer: [bool; DISPLAY_SIZE],
     pub redraw: bool,
 
-    pub pressed_keys: [bool; 16],
-
-    debug: bool
+    pub keypad: Keypad,
 
-    // pub exec_history: Vec<u16>
+    pub watchpoints: Vec<Watchpoint>,
+    // Cell so that watched reads can be recorded from `&self` accessors.
+    watch_hit: Cell<Option<WatchHit>>,
+    // Address and opcode of the instruction currently executing.
+    op_pc: u16,
+    op: u16,
+    // Memory writes made by the last instruction, as (address, value).
+    writes: Vec<(u16, u8)>
 }
 
 impl CPU {
-    pub fn load_rom(&mut self, rom: Vec<u8>) {
-        self.memory[0x200..0x200+rom.len()].copy_from_slice(&rom)
+    pub fn load_rom(&mut self, rom: Vec<u8>) -> Result<(), String> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(format!("ROM is {} bytes, only {MAX_ROM_SIZE} fit in memory after 0x{PROGRAM_START:03x}", rom.len()));
+        }
+        self.memory[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(&rom);
+        Ok(())
     }
 
-    pub fn tick(&mut self, keys: [bool; 16]) {
+    pub fn tick(&mut self) -> Result<(), Fault> {
+        self.writes.clear();
 
-        self.pressed_keys = keys;
+        if self.pc as usize + 1 >= self.memory.len() {
+            self.op_pc = self.pc;
+            return Err(Fault::PcOutOfRange { pc: self.pc });
+        }
+        let b1 = self.memory[self.pc as usize] as u16;
+        let b2 = self.memory[self.pc as usize + 1] as u16;
+        self.pc += 2;
 
-        self.redraw = false;
+        let op: u16 = (b1 << 8) | b2;
+        self.op_pc = self.pc - 2;
+        self.op = op;
 
+        // Leave PC on the faulting instruction so it can be inspected.
+        let result = self.execute(op);
+        if result.is_err() {
+            self.pc = self.op_pc;
+        }
+        result
+    }
+
+    // Called at 60Hz, independently of the instruction rate.
+    pub fn tick_timers(&mut self) {
         if self.dt > 0 {
             self.dt -= 1;
         }
         if self.st > 0 {
             self.st -= 1;
         }
+    }
 
-        let b1 = self.memory[self.pc as usize] as u16;
-        let b2 = self.memory[self.pc as usize + 1] as u16;
-        self.pc += 2;
-
-        let op: u16 = (b1 << 8) | b2;
-
-        let args = Instruction::new(op as u16);
+    fn execute(&mut self, op: u16) -> Result<(), Fault> {
+        let args = Instruction::new(op);
+        let invalid = Fault::InvalidOpcode { pc: self.op_pc, op };
 
-        
         let opcode = op >> 12;
-        if self.debug {
-            println!("[0x{:0x}] [I: 0x{:0x}] Op: 0x{op:0x}, {args}", self.pc - 2, self.I);
-            // println!("{:?}", &self.memory[0x400..0x420]);
-            println!("V: {:0x?}\n", self.V);
-        }
-        // if opcode != 0x1 {
-            // println!("0x{op:0x}");
-            // if !self.exec_history.contains(&opcode) {
-                // self.exec_history.push(opcode);
-                // println!("0x{op:0x}");
-            // }
-            // println!("{:?}", &args);
-            // println!("{:?}", self.V);
-        // }
         match opcode {
             0x0 => {
                 match args.kk {
                     0xE0 => self.cls(),
+                    0xEE if self.sp == 0 => return Err(Fault::StackUnderflow { pc: self.op_pc }),
                     0xEE => self.ret(),
-                    0x00 => return,
-                    _ => panic!("Invalid op {op:0x}")
+                    0x00 => (),
+                    _ => return Err(invalid)
                 }
             }
             0x1 => self.jp(args.nnn),
+            0x2 if self.sp as usize == self.stack.len() => return Err(Fault::StackOverflow { pc: self.op_pc }),
             0x2 => self.call(args.nnn),
             0x3 => self.se_x(args.x, args.kk),
             0x4 => self.sne_x(args.x, args.kk),
@@ -122,7 +162,7 @@ impl CPU {
                     0x6 => self.shr(args.x, args.y),
                     0x7 => self.subn(args.x, args.y),
                     0xE => self.shl(args.x, args.y),
-                    _ => panic!("Invalid op 0x{op:0x}")
+                    _ => return Err(invalid)
                 }
             },
             0xA => self.ld_i(args.nnn),
@@ -133,7 +173,7 @@ impl CPU {
                 match args.kk {
                     0x9E => self.skp(args.x),
                     0xA1 => self.sknp(args.x),
-                    _ => panic!("Invalid op 0x{op:0x}")
+                    _ => return Err(invalid)
                 }
             }
             0xF => {
@@ -147,32 +187,140 @@ impl CPU {
                     0x55 => self.ld_ivx(args.x),
                     0x65 => self.ld_vxi(args.x),
                     0x1E => self.add_i(args.x),
-                    _ => panic!("Invalid op 0x{op:0x}")
+                    _ => return Err(invalid)
                 }
             }
-            o => {
-                println!("0x{o:0x} not implemented or invalid.");
-            }
+            _ => return Err(invalid)
+        }
+        Ok(())
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn i(&self) -> u16 {
+        self.I
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    pub fn v(&self) -> [u8; 16] {
+        self.V
+    }
+
+    // Return addresses currently on the stack, oldest first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn dt(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn st(&self) -> u8 {
+        self.st
+    }
+
+    // Setters for external tooling, these bypass watchpoints.
+    pub fn set_v(&mut self, x: u8, val: u8) {
+        self.V[x as usize] = val;
+    }
+
+    pub fn set_i(&mut self, val: u16) {
+        self.I = val;
+    }
+
+    pub fn set_pc(&mut self, val: u16) {
+        self.pc = val;
+    }
+
+    pub fn set_sp(&mut self, val: u8) {
+        self.sp = val.min(self.stack.len() as u8);
+    }
+
+    pub fn set_dt(&mut self, val: u8) {
+        self.dt = val;
+    }
+
+    pub fn set_st(&mut self, val: u8) {
+        self.st = val;
+    }
+
+    // The opcode at PC, i.e. the next instruction to be executed. Bytes past the end of memory read as 0.
+    pub fn next_op(&self) -> u16 {
+        let byte = |addr: usize| self.memory.get(addr).copied().unwrap_or(0) as u16;
+        let pc = self.pc as usize;
+        (byte(pc) << 8) | byte(pc + 1)
+    }
+
+    // Returns the first watchpoint hit since the last call, if any.
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    fn watch(&self, target: Target, write: bool, old: u16, new: u16) {
+        if self.watch_hit.get().is_some() {
+            return;
+        }
+        if let Some(index) = self.watchpoints.iter().position(|w| w.matches(target, write)) {
+            self.watch_hit.set(Some(WatchHit {
+                index, pc: self.op_pc, op: self.op, target, write, old, new
+            }));
         }
     }
 
     fn read_v(&self, addr: u8) -> u8 {
-        self.V[addr as usize]
+        let val = self.V[addr as usize];
+        self.watch(Target::V(addr), false, val as u16, val as u16);
+        val
     }
 
     fn write_v(&mut self, addr: u8, val: u8) {
+        self.watch(Target::V(addr), true, self.V[addr as usize] as u16, val as u16);
         self.V[addr as usize] = val
     }
 
+    fn read_i(&self) -> u16 {
+        self.watch(Target::I, false, self.I, self.I);
+        self.I
+    }
+
+    fn write_i(&mut self, val: u16) {
+        self.watch(Target::I, true, self.I, val);
+        self.I = val;
+    }
+
+    // Addresses past the end of memory wrap around, I can go up to 0xFFFF.
+    fn read_mem(&self, addr: u16) -> u8 {
+        let addr = addr & 0xFFF;
+        let val = self.memory[addr as usize];
+        self.watch(Target::Memory { start: addr, end: addr }, false, val as u16, val as u16);
+        val
+    }
+
+    fn write_mem(&mut self, addr: u16, val: u8) {
+        let addr = addr & 0xFFF;
+        self.watch(Target::Memory { start: addr, end: addr }, true, self.memory[addr as usize] as u16, val as u16);
+        self.memory[addr as usize] = val;
+        self.writes.push((addr, val));
+    }
+
+    pub fn last_writes(&self) -> &[(u16, u8)] {
+        &self.writes
+    }
+
+    // SP is the amount of return addresses on the stack, all 16 entries can be used.
     fn stack_pop(&mut self) -> u16 {
-        let val = self.stack[self.sp as usize];
         self.sp -= 1;
-        val
+        self.stack[self.sp as usize]
     }
 
     fn stack_push(&mut self, val: u16) {
-        self.sp += 1;
         self.stack[self.sp as usize] = val;
+        self.sp += 1;
     }
 
 }
@@ -180,7 +328,8 @@ impl CPU {
 impl CPU {
     // 0x00E0
     pub fn cls(&mut self) {
-        self.vbuffer = [false; DISPLAY_SIZE]
+        self.vbuffer = [false; DISPLAY_SIZE];
+        self.redraw = true;
     }
 
     // 0x00EE
@@ -246,7 +395,7 @@ impl CPU {
 
     // 0xAnnn
     pub fn ld_i(&mut self, addr: u16) {
-        self.I = addr;
+        self.write_i(addr);
     }
 
     // 0xFx07
@@ -256,13 +405,10 @@ impl CPU {
 
     // 0xFx0A
     pub fn ld_xk(&mut self, x: u8) {
-        for (i, k) in self.pressed_keys.iter().enumerate() {
-            if *k {
-                self.write_v(x, i as u8);
-                return;
-            }
+        match self.keypad.wait_key() {
+            Some(key) => self.write_v(x, key),
+            None => self.pc -= 2
         }
-        self.pc -= 2;
     }
 
     // 0xFx15 
@@ -277,41 +423,47 @@ impl CPU {
 
     // 0xFx29
     pub fn ld_ix(&mut self, x: u8) {
-        self.I = self.read_v(x) as u16 * 5;
+        self.write_i(self.read_v(x) as u16 * 5);
     }
 
     // 0xFx33
     pub fn ld_ix_bcd(&mut self, x: u8) {
         let vx = self.read_v(x);
-        self.memory[self.I as usize] = vx / 100;
-        self.memory[self.I as usize + 1] = (vx % 100) / 10;
-        self.memory[self.I as usize + 2] = vx % 10;
+        let i = self.read_i();
+        self.write_mem(i, vx / 100);
+        self.write_mem(i.wrapping_add(1), (vx % 100) / 10);
+        self.write_mem(i.wrapping_add(2), vx % 10);
     }
 
     // 0xFx55
     pub fn ld_ivx(&mut self, x: u8) {
         for i in 0..=x {
             let vi = self.read_v(i);
-            let idx = self.I + i as u16;
-            self.memory[idx as usize] = vi;
+            let idx = self.read_i().wrapping_add(i as u16);
+            self.write_mem(idx, vi);
         }
         if self.quirks.mem_inc {
-            self.I += (x + 1) as u16;
+            self.write_i(self.I.wrapping_add(self.mem_inc_by(x)));
         }
     }
 
     // 0xFx65
     pub fn ld_vxi(&mut self, x: u8) {
         for i in 0..=x {
-            let idx = self.I + i as u16;
-            let mi = self.memory[idx as usize];
+            let idx = self.read_i().wrapping_add(i as u16);
+            let mi = self.read_mem(idx);
             self.write_v(i, mi)
         }
         if self.quirks.mem_inc {
-            self.I += (x + 1) as u16;
+            self.write_i(self.I.wrapping_add(self.mem_inc_by(x)));
         }
     }
 
+    // How far FX55 and FX65 move I with the mem_inc quirk.
+    fn mem_inc_by(&self, x: u8) -> u16 {
+        if self.quirks.mem_inc_x { x as u16 } else { x as u16 + 1 }
+    }
+
     // 0x7xkk
     pub fn add(&mut self, x: u8, byte: u8) {
         let vx = self.read_v(x) as u16;
@@ -322,25 +474,25 @@ impl CPU {
     
     // 0x8xy1
     pub fn or(&mut self, x: u8, y: u8) {
-        self.V[x as usize] |= self.read_v(y);
+        self.write_v(x, self.read_v(x) | self.read_v(y));
         if self.quirks.vf_reset {
-            self.V[0xF] = 0;
+            self.write_v(0xF, 0);
         }
     }
 
     // 0x8xy2
     pub fn and(&mut self, x: u8, y: u8) {
-        self.V[x as usize] &= self.read_v(y);
+        self.write_v(x, self.read_v(x) & self.read_v(y));
         if self.quirks.vf_reset {
-            self.V[0xF] = 0;
+            self.write_v(0xF, 0);
         }
     }
 
     // 0x8xy3
     pub fn xor(&mut self, x: u8, y: u8) {
-        self.V[x as usize] ^= self.read_v(y);
+        self.write_v(x, self.read_v(x) ^ self.read_v(y));
         if self.quirks.vf_reset {
-            self.V[0xF] = 0;
+            self.write_v(0xF, 0);
         }
     }
 
@@ -352,46 +504,49 @@ impl CPU {
         self.write_v(x, sum as u8);
 
         if sum > 0xFF {
-            self.V[0xF] = 1;
+            self.write_v(0xF, 1);
         }
         else {
-            self.V[0xF] = 0
+            self.write_v(0xF, 0);
         }
     }
 
     // 0xFx1E
     pub fn add_i(&mut self, x: u8) {
-        self.I += self.read_v(x) as u16;
-        self.V[0x0F] = if self.I > 0x0F00 { 1 } else { 0 };
+        let sum = self.read_i().wrapping_add(self.read_v(x) as u16);
+        self.write_i(sum);
+        // Other interpreters leave VF alone, Spacefight 2091! relies on the Amiga's flag.
+        if self.quirks.i_overflow {
+            self.write_v(0xF, if sum > 0x0FFF { 1 } else { 0 });
+        }
     }
 
     // 0x8xy5
     pub fn sub(&mut self, x: u8, y: u8) {
-        let vx = self.read_v(x) as u16;
-        let vy = self.read_v(y) as u16;
-        let sum = vx.wrapping_sub(vy);
-        self.write_v(x, sum as u8);
+        let vx = self.read_v(x);
+        let vy = self.read_v(y);
+        self.write_v(x, vx.wrapping_sub(vy));
 
-        if self.read_v(x) > self.read_v(y) {
-            self.V[0xF] = 1;
+        // NOT borrow, from the operands rather than the result. Written last so that for 8FY5 the flag wins.
+        if vx >= vy {
+            self.write_v(0xF, 1);
         }
         else {
-            self.V[0xF] = 0;
+            self.write_v(0xF, 0);
         }
     }
 
     // 0x8xy7
     pub fn subn(&mut self, x: u8, y: u8) {
-        let vx = self.read_v(x) as u16;
-        let vy = self.read_v(y) as u16;
-        let sum = vy.wrapping_sub(vx);
-        self.write_v(x, sum as u8);
+        let vx = self.read_v(x);
+        let vy = self.read_v(y);
+        self.write_v(x, vy.wrapping_sub(vx));
 
-        if self.read_v(y) > self.read_v(x) {
-            self.V[0xF] = 1;
+        if vy >= vx {
+            self.write_v(0xF, 1);
         }
         else {
-            self.V[0xF] = 0;
+            self.write_v(0xF, 0);
         }
     }
 
@@ -404,8 +559,8 @@ impl CPU {
         let target = if self.quirks.shift_x { x } else { y }; 
 
         let flag = self.read_v(target) & 1;
-        self.V[x as usize] = self.read_v(target) >> 1;
-        self.V[0xF] = flag;
+        self.write_v(x, self.read_v(target) >> 1);
+        self.write_v(0xF, flag);
     }
 
     // 0x8xyE
@@ -413,30 +568,33 @@ impl CPU {
         let target = if self.quirks.shift_x { x } else { y }; 
 
         let flag = self.read_v(target) >> 7;
-        self.V[x as usize] = self.read_v(target) << 1;
-        self.V[0xF] = flag;
+        self.write_v(x, self.read_v(target) << 1);
+        self.write_v(0xF, flag);
     }
 
     // 0xCxkk
     pub fn rnd(&mut self, x: u8, kk: u8) {
-        self.V[x as usize] = random::<u8>() & kk;
+        self.write_v(x, random::<u8>() & kk);
     }
 
     // 0xDxyn
     pub fn drw(&mut self, x: u8, y: u8, n: u8) {
-        self.V[0x0F] = 0;
+        // The position is read before VF is written, so DFYN draws at the old VF.
+        // The starting position always wraps, only the rest of the sprite is clipped.
+        let cx = self.read_v(x) as usize % DISPLAY_WIDTH;
+        let cy = self.read_v(y) as usize % DISPLAY_HEIGHT;
+        let mut collision = 0;
         for byte in 0..n {
+            let sprite_row = self.read_mem(self.read_i().wrapping_add(byte as u16));
             for bit in 0..8 {
-                let sprite_bit = (self.memory[self.I as usize + byte as usize] >> (7 - bit)) & 1;
+                let sprite_bit = (sprite_row >> (7 - bit)) & 1;
 
-                let mut pos_x = (self.read_v(x) + bit) as usize;
-                let mut pos_y = (self.read_v(y) + byte) as usize;
+                let mut pos_x = cx + bit;
+                let mut pos_y = cy + byte as usize;
                 
-                // Doesn't work idk
-                // if (pos_x >= DISPLAY_WIDTH || pos_y >= DISPLAY_HEIGHT) && self.quirks.clipping {
-                //     // println!("Clipping: X{pos_x} Y{pos_y}");
-                //     continue 'pixel;
-                // }
+                if (pos_x >= DISPLAY_WIDTH || pos_y >= DISPLAY_HEIGHT) && self.quirks.clipping {
+                    continue;
+                }
 
                 pos_x %= DISPLAY_WIDTH;
                 pos_y %= DISPLAY_HEIGHT;
@@ -445,11 +603,12 @@ impl CPU {
 
                 let old_bit = if self.vbuffer[pixel_index] {1} else {0};
 
-                // self.V[0xF] |= sprite_bit & old_bit;
+                collision |= sprite_bit & old_bit;
                 self.vbuffer[pixel_index] = old_bit ^ sprite_bit != 0;
 
             }
         }
+        self.write_v(0xF, collision);
         self.redraw = true;
     }
     // pub fn drw(&mut self, x: u8, y: u8, n: u8) {
@@ -490,14 +649,14 @@ impl CPU {
  
     // 0xEx9E
     pub fn skp(&mut self, x: u8) {
-        if self.pressed_keys[self.read_v(x) as usize] {
+        if self.keypad.is_pressed(self.read_v(x)) {
             self.pc += 2;
         }
     }
 
     // 0xExA1
     pub fn sknp(&mut self, x: u8) {
-        if !self.pressed_keys[self.read_v(x) as usize] {
+        if !self.keypad.is_pressed(self.read_v(x)) {
             self.pc += 2;
         }
     }
@@ -505,7 +664,7 @@ impl CPU {
 }
 
 impl CPU {
-    pub fn new(quirks: Quirk, debug: bool) -> Self {
+    pub fn new(quirks: Quirk) -> Self {
         let mut cpu = CPU {
             memory: [0; 0x1000],
             V: [0; 16],
@@ -515,14 +674,432 @@ impl CPU {
             stack: [0; 16],
             dt: 0,
             st: 0,
-            pressed_keys: [false; 16],
+            keypad: Keypad::new(quirks.key_release),
             vbuffer: [false; DISPLAY_SIZE],
             redraw: true,
             quirks,
-            debug
-            // exec_history: Vec::new()
+            watchpoints: Vec::new(),
+            watch_hit: Cell::new(None),
+            op_pc: 0,
+            op: 0,
+            writes: Vec::new()
         };
         cpu.memory[..FONT_SET.len()].copy_from_slice(&FONT_SET);
         cpu
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_QUIRKS: Quirk = Quirk {
+        vf_reset: false,
+        mem_inc: false,
+        mem_inc_x: false,
+        display_wait: false,
+        clipping: false,
+        shift_x: false,
+        jump_vx: false,
+        key_release: false,
+        i_overflow: false
+    };
+
+    // CPU in a given state, built up before running an instruction on it.
+    struct Setup {
+        cpu: CPU
+    }
+
+    impl Setup {
+        fn new() -> Self {
+            Setup { cpu: CPU::new(NO_QUIRKS) }
+        }
+
+        fn quirks(quirks: Quirk) -> Self {
+            Setup { cpu: CPU::new(quirks) }
+        }
+
+        fn v(mut self, x: u8, val: u8) -> Self {
+            self.cpu.V[x as usize] = val;
+            self
+        }
+
+        fn i(mut self, val: u16) -> Self {
+            self.cpu.I = val;
+            self
+        }
+
+        fn mem(mut self, addr: u16, bytes: &[u8]) -> Self {
+            self.cpu.memory[addr as usize..addr as usize + bytes.len()].copy_from_slice(bytes);
+            self
+        }
+
+        // Places `op` at PC and executes it.
+        fn run(mut self, op: u16) -> CPU {
+            exec(&mut self.cpu, op);
+            self.cpu
+        }
+    }
+
+    fn exec(cpu: &mut CPU, op: u16) {
+        let pc = cpu.pc as usize;
+        cpu.memory[pc..pc + 2].copy_from_slice(&op.to_be_bytes());
+        cpu.tick().unwrap_or_else(|fault| panic!("{fault}"));
+    }
+
+    fn pixel(cpu: &CPU, x: usize, y: usize) -> bool {
+        cpu.vbuffer[y * DISPLAY_WIDTH + x]
+    }
+
+    #[test]
+    fn cls_clears_screen() {
+        let mut cpu = Setup::new().run(0x00E0);
+        cpu.vbuffer[5] = true;
+        exec(&mut cpu, 0x00E0);
+        assert!(cpu.vbuffer.iter().all(|p| !p));
+        assert!(cpu.redraw);
+    }
+
+    #[test]
+    fn call_and_ret() {
+        let mut cpu = Setup::new().run(0x2300);
+        assert_eq!(cpu.pc(), 0x300);
+        assert_eq!(cpu.stack(), &[0x202]);
+        exec(&mut cpu, 0x00EE);
+        assert_eq!(cpu.pc(), 0x202);
+        assert_eq!(cpu.sp(), 0);
+    }
+
+    #[test]
+    fn ret_on_empty_stack_faults() {
+        let mut cpu = CPU::new(NO_QUIRKS);
+        cpu.memory[0x200..0x202].copy_from_slice(&[0x00, 0xEE]);
+        assert!(matches!(cpu.tick(), Err(Fault::StackUnderflow { pc: 0x200 })));
+        assert_eq!(cpu.pc(), 0x200);
+    }
+
+    #[test]
+    fn call_on_full_stack_faults() {
+        let mut cpu = CPU::new(NO_QUIRKS);
+        for _ in 0..16 {
+            exec(&mut cpu, 0x2200);
+        }
+        assert_eq!(cpu.stack().len(), 16);
+        assert!(matches!(cpu.tick(), Err(Fault::StackOverflow { pc: 0x200 })));
+    }
+
+    #[test]
+    fn pc_past_memory_faults() {
+        let mut cpu = Setup::new().run(0x1FFF);
+        assert_eq!(cpu.next_op(), 0x0000);
+        assert!(matches!(cpu.tick(), Err(Fault::PcOutOfRange { pc: 0xFFF })));
+        assert_eq!(cpu.pc(), 0xFFF);
+    }
+
+    #[test]
+    fn invalid_opcode_faults() {
+        for op in [0x0123, 0x8008, 0xE0FF, 0xF0FF] {
+            let mut cpu = CPU::new(NO_QUIRKS);
+            cpu.memory[0x200..0x202].copy_from_slice(&u16::to_be_bytes(op));
+            assert!(matches!(cpu.tick(), Err(Fault::InvalidOpcode { pc: 0x200, op: o }) if o == op), "{op:04x}");
+        }
+    }
+
+    #[test]
+    fn jp() {
+        assert_eq!(Setup::new().run(0x1ABC).pc(), 0xABC);
+    }
+
+    #[test]
+    fn jp_v0() {
+        let cpu = Setup::new().v(0, 0x10).v(3, 0x20).run(0xB300);
+        assert_eq!(cpu.pc(), 0x310);
+    }
+
+    #[test]
+    fn jp_v0_jump_vx_quirk() {
+        let quirks = Quirk { jump_vx: true, ..NO_QUIRKS };
+        let cpu = Setup::quirks(quirks).v(0, 0x10).v(3, 0x20).run(0xB300);
+        assert_eq!(cpu.pc(), 0x320);
+    }
+
+    #[test]
+    fn skips() {
+        // (op, skips) with V1 = 0x42, V2 = 0x42, V3 = 0x07.
+        let cases = [
+            (0x3142, true), (0x3143, false),
+            (0x4142, false), (0x4143, true),
+            (0x5120, true), (0x5130, false),
+            (0x9120, false), (0x9130, true)
+        ];
+        for (op, skips) in cases {
+            let cpu = Setup::new().v(1, 0x42).v(2, 0x42).v(3, 0x07).run(op);
+            assert_eq!(cpu.pc(), if skips { 0x204 } else { 0x202 }, "{op:04x}");
+        }
+    }
+
+    #[test]
+    fn skp_and_sknp() {
+        let mut setup = Setup::new().v(1, 0xA);
+        setup.cpu.keypad.press(0xA);
+        assert_eq!(setup.run(0xE19E).pc(), 0x204);
+
+        let mut setup = Setup::new().v(1, 0xA);
+        setup.cpu.keypad.press(0xA);
+        assert_eq!(setup.run(0xE1A1).pc(), 0x202);
+
+        assert_eq!(Setup::new().v(1, 0xA).run(0xE19E).pc(), 0x202);
+        assert_eq!(Setup::new().v(1, 0xA).run(0xE1A1).pc(), 0x204);
+    }
+
+    #[test]
+    fn ld_xk_waits_for_key() {
+        let mut cpu = Setup::new().run(0xF30A);
+        assert_eq!(cpu.pc(), 0x200);
+        cpu.keypad.press(0x7);
+        exec(&mut cpu, 0xF30A);
+        assert_eq!(cpu.pc(), 0x202);
+        assert_eq!(cpu.v()[3], 0x7);
+    }
+
+    #[test]
+    fn ld_xk_key_release_quirk() {
+        let mut cpu = Setup::quirks(Quirk { key_release: true, ..NO_QUIRKS }).run(0xF30A);
+        cpu.keypad.press(0x7);
+        exec(&mut cpu, 0xF30A);
+        assert_eq!(cpu.pc(), 0x200);
+        cpu.keypad.release(0x7);
+        exec(&mut cpu, 0xF30A);
+        assert_eq!(cpu.pc(), 0x202);
+        assert_eq!(cpu.v()[3], 0x7);
+    }
+
+    #[test]
+    fn loads() {
+        assert_eq!(Setup::new().run(0x6A42).v()[0xA], 0x42);
+        assert_eq!(Setup::new().v(2, 0x99).run(0x8120).v()[1], 0x99);
+        assert_eq!(Setup::new().run(0xA123).i(), 0x123);
+    }
+
+    #[test]
+    fn timers() {
+        let mut setup = Setup::new();
+        setup.cpu.dt = 0x30;
+        assert_eq!(setup.run(0xF407).v()[4], 0x30);
+        assert_eq!(Setup::new().v(4, 0x20).run(0xF415).dt(), 0x20);
+        assert_eq!(Setup::new().v(4, 0x10).run(0xF418).st(), 0x10);
+    }
+
+    #[test]
+    fn ld_ix_points_at_font() {
+        let cpu = Setup::new().v(1, 0xA).run(0xF129);
+        assert_eq!(cpu.i(), 0xA * 5);
+        assert_eq!(cpu.memory[cpu.i() as usize..cpu.i() as usize + 5], FONT_SET[50..55]);
+    }
+
+    #[test]
+    fn ld_ix_bcd() {
+        let cpu = Setup::new().v(1, 254).i(0x300).run(0xF133);
+        assert_eq!(cpu.memory[0x300..0x303], [2, 5, 4]);
+        assert_eq!(cpu.i(), 0x300);
+        let cpu = Setup::new().v(1, 7).i(0x300).run(0xF133);
+        assert_eq!(cpu.memory[0x300..0x303], [0, 0, 7]);
+    }
+
+    #[test]
+    fn ld_ivx_and_ld_vxi() {
+        for mem_inc in [false, true] {
+            let quirks = Quirk { mem_inc, ..NO_QUIRKS };
+            let cpu = Setup::quirks(quirks).v(0, 1).v(1, 2).v(2, 3).v(3, 4).i(0x300).run(0xF255);
+            assert_eq!(cpu.memory[0x300..0x304], [1, 2, 3, 0]);
+            assert_eq!(cpu.i(), if mem_inc { 0x303 } else { 0x300 });
+
+            let cpu = Setup::quirks(quirks).mem(0x300, &[9, 8, 7, 6]).i(0x300).run(0xF265);
+            assert_eq!(cpu.v()[..4], [9, 8, 7, 0]);
+            assert_eq!(cpu.i(), if mem_inc { 0x303 } else { 0x300 });
+        }
+    }
+
+    #[test]
+    fn mem_inc_x_quirk() {
+        let quirks = Quirk { mem_inc: true, mem_inc_x: true, ..NO_QUIRKS };
+        assert_eq!(Setup::quirks(quirks).i(0x300).run(0xF255).i(), 0x302);
+        assert_eq!(Setup::quirks(quirks).i(0x300).run(0xF265).i(), 0x302);
+        // Only changes how far I moves, not whether it does.
+        let quirks = Quirk { mem_inc_x: true, ..NO_QUIRKS };
+        assert_eq!(Setup::quirks(quirks).i(0x300).run(0xF255).i(), 0x300);
+    }
+
+    #[test]
+    fn add_wraps_without_flag() {
+        let cpu = Setup::new().v(1, 0xFF).v(0xF, 0x42).run(0x7102);
+        assert_eq!(cpu.v()[1], 0x01);
+        assert_eq!(cpu.v()[0xF], 0x42);
+    }
+
+    #[test]
+    fn logic() {
+        // (op, result) with V1 = 0b1100, V2 = 0b1010.
+        for (op, result) in [(0x8121, 0b1110), (0x8122, 0b1000), (0x8123, 0b0110)] {
+            for vf_reset in [false, true] {
+                let quirks = Quirk { vf_reset, ..NO_QUIRKS };
+                let cpu = Setup::quirks(quirks).v(1, 0b1100).v(2, 0b1010).v(0xF, 0x42).run(op);
+                assert_eq!(cpu.v()[1], result, "{op:04x}");
+                assert_eq!(cpu.v()[0xF], if vf_reset { 0 } else { 0x42 }, "{op:04x}");
+            }
+        }
+    }
+
+    #[test]
+    fn add_xy() {
+        let cpu = Setup::new().v(1, 0xFF).v(2, 0x02).run(0x8124);
+        assert_eq!((cpu.v()[1], cpu.v()[0xF]), (0x01, 1));
+        let cpu = Setup::new().v(1, 0x10).v(2, 0x02).v(0xF, 1).run(0x8124);
+        assert_eq!((cpu.v()[1], cpu.v()[0xF]), (0x12, 0));
+    }
+
+    #[test]
+    fn add_xy_flag_overwrites_vf_result() {
+        let cpu = Setup::new().v(0xF, 0xFF).v(2, 0x02).run(0x8F24);
+        assert_eq!(cpu.v()[0xF], 1);
+        let cpu = Setup::new().v(0xF, 0x10).v(2, 0x02).run(0x8F24);
+        assert_eq!(cpu.v()[0xF], 0);
+        let cpu = Setup::new().v(1, 0x80).v(0xF, 0x80).run(0x81F4);
+        assert_eq!((cpu.v()[1], cpu.v()[0xF]), (0x00, 1));
+    }
+
+    #[test]
+    fn sub_and_subn_results() {
+        assert_eq!(Setup::new().v(1, 0x05).v(2, 0x03).run(0x8125).v()[1], 0x02);
+        assert_eq!(Setup::new().v(1, 0x03).v(2, 0x05).run(0x8125).v()[1], 0xFE);
+        assert_eq!(Setup::new().v(1, 0x03).v(2, 0x05).run(0x8127).v()[1], 0x02);
+        assert_eq!(Setup::new().v(1, 0x05).v(2, 0x03).run(0x8127).v()[1], 0xFE);
+    }
+
+    #[test]
+    fn sub_flags() {
+        // (op, V1, V2, VF) where 8XY5 is V1 - V2 and 8XY7 is V2 - V1, VF is 1 without a borrow.
+        let cases = [
+            (0x8125, 0x05, 0x03, 1), (0x8125, 0x03, 0x05, 0), (0x8125, 0x04, 0x04, 1),
+            (0x8127, 0x03, 0x05, 1), (0x8127, 0x05, 0x03, 0), (0x8127, 0x04, 0x04, 1)
+        ];
+        for (op, v1, v2, flag) in cases {
+            let cpu = Setup::new().v(1, v1).v(2, v2).v(0xF, 0x42).run(op);
+            assert_eq!(cpu.v()[0xF], flag, "{op:04x} with {v1}, {v2}");
+        }
+    }
+
+    #[test]
+    fn sub_flag_overwrites_vf_result() {
+        let cpu = Setup::new().v(0xF, 0x05).v(2, 0x03).run(0x8F25);
+        assert_eq!(cpu.v()[0xF], 1);
+        let cpu = Setup::new().v(0xF, 0x03).v(2, 0x05).run(0x8F25);
+        assert_eq!(cpu.v()[0xF], 0);
+        let cpu = Setup::new().v(0xF, 0x05).v(2, 0x03).run(0x8F27);
+        assert_eq!(cpu.v()[0xF], 0);
+        // VF as the subtrahend is read before the flag replaces it.
+        let cpu = Setup::new().v(1, 0x05).v(0xF, 0x03).run(0x81F5);
+        assert_eq!((cpu.v()[1], cpu.v()[0xF]), (0x02, 1));
+    }
+
+    #[test]
+    fn add_i() {
+        let cpu = Setup::new().i(0x0FF0).v(1, 0x20).v(0xF, 0x42).run(0xF11E);
+        assert_eq!(cpu.i(), 0x1010);
+        assert_eq!(cpu.v()[0xF], 0x42);
+    }
+
+    #[test]
+    fn add_i_overflow_quirk() {
+        let quirks = Quirk { i_overflow: true, ..NO_QUIRKS };
+        let cpu = Setup::quirks(quirks).i(0x0FF0).v(1, 0x20).run(0xF11E);
+        assert_eq!((cpu.i(), cpu.v()[0xF]), (0x1010, 1));
+        let cpu = Setup::quirks(quirks).i(0x0F00).v(1, 0x20).v(0xF, 1).run(0xF11E);
+        assert_eq!((cpu.i(), cpu.v()[0xF]), (0x0F20, 0));
+    }
+
+    #[test]
+    fn shifts() {
+        for shift_x in [false, true] {
+            let quirks = Quirk { shift_x, ..NO_QUIRKS };
+            // V1 = 0b0110, V2 = 0b1000_0001.
+            let cpu = Setup::quirks(quirks).v(1, 0b0110).v(2, 0b1000_0001).run(0x8126);
+            assert_eq!((cpu.v()[1], cpu.v()[0xF]), if shift_x { (0b0011, 0) } else { (0b0100_0000, 1) });
+            assert_eq!(cpu.v()[2], 0b1000_0001);
+
+            let cpu = Setup::quirks(quirks).v(1, 0b0110).v(2, 0b1000_0001).run(0x812E);
+            assert_eq!((cpu.v()[1], cpu.v()[0xF]), if shift_x { (0b1100, 0) } else { (0b0000_0010, 1) });
+        }
+    }
+
+    #[test]
+    fn shift_flag_overwrites_vf_result() {
+        let cpu = Setup::new().v(0xF, 0b0000_0011).run(0x8FF6);
+        assert_eq!(cpu.v()[0xF], 1);
+        let cpu = Setup::new().v(0xF, 0b0100_0000).run(0x8FFE);
+        assert_eq!(cpu.v()[0xF], 0);
+    }
+
+    #[test]
+    fn rnd_is_masked() {
+        for _ in 0..32 {
+            assert_eq!(Setup::new().run(0xC100).v()[1], 0);
+            assert_eq!(Setup::new().run(0xC10F).v()[1] & 0xF0, 0);
+        }
+    }
+
+    #[test]
+    fn drw_xors_and_reports_collision() {
+        let mut cpu = Setup::new().v(1, 2).v(2, 3).mem(0x300, &[0b1100_0000, 0b1000_0000]).i(0x300).run(0xD122);
+        assert!(pixel(&cpu, 2, 3) && pixel(&cpu, 3, 3) && pixel(&cpu, 2, 4));
+        assert!(!pixel(&cpu, 3, 4));
+        assert_eq!(cpu.v()[0xF], 0);
+
+        exec(&mut cpu, 0xD122);
+        assert!(cpu.vbuffer.iter().all(|p| !p));
+        assert_eq!(cpu.v()[0xF], 1);
+    }
+
+    #[test]
+    fn drw_wraps_around_edges() {
+        let cpu = Setup::new().v(1, 63).v(2, 31).mem(0x300, &[0b1100_0000, 0b1100_0000]).i(0x300).run(0xD122);
+        assert!(pixel(&cpu, 63, 31) && pixel(&cpu, 0, 31) && pixel(&cpu, 63, 0) && pixel(&cpu, 0, 0));
+        // Coordinates past the screen start over too.
+        let cpu = Setup::new().v(1, 0xFF).v(2, 0xFF).mem(0x300, &[0b1000_0000]).i(0x300).run(0xD121);
+        assert!(pixel(&cpu, 63, 31));
+    }
+
+    #[test]
+    fn drw_clipping_quirk() {
+        let quirks = Quirk { clipping: true, ..NO_QUIRKS };
+        let cpu = Setup::quirks(quirks).v(1, 63).v(2, 31).mem(0x300, &[0b1100_0000, 0b1100_0000]).i(0x300).run(0xD122);
+        assert!(pixel(&cpu, 63, 31));
+        assert_eq!(cpu.vbuffer.iter().filter(|p| **p).count(), 1);
+        // The position still wraps, 0xFF is 63, 31.
+        let cpu = Setup::quirks(quirks).v(1, 0xFF).v(2, 0xFF).mem(0x300, &[0b1100_0000]).i(0x300).run(0xD121);
+        assert!(pixel(&cpu, 63, 31) && !pixel(&cpu, 0, 31));
+    }
+
+    #[test]
+    fn memory_wraps_when_i_is_past_the_end() {
+        // FX1E can take I past 0xFFF, reads and writes then go to the start of memory.
+        let quirks = Quirk { mem_inc: true, ..NO_QUIRKS };
+        let cpu = Setup::quirks(quirks).v(0, 1).v(1, 2).i(0xFFFF).run(0xF155);
+        assert_eq!(cpu.memory[0xFFF], 1);
+        assert_eq!(cpu.memory[0x000], 2);
+        assert_eq!(cpu.i(), 0x0001);
+
+        let cpu = Setup::new().v(1, 123).i(0xFFFF).run(0xF133);
+        assert_eq!([cpu.memory[0xFFF], cpu.memory[0x000], cpu.memory[0x001]], [1, 2, 3]);
+
+        let cpu = Setup::new().mem(0x000, &[0b1000_0000]).mem(0xFFF, &[0b0100_0000]).i(0xFFFF).run(0xD002);
+        assert!(pixel(&cpu, 1, 0) && pixel(&cpu, 0, 1));
+    }
+
+    #[test]
+    fn drw_reads_position_from_vf_before_setting_it() {
+        let cpu = Setup::new().v(0xF, 10).v(1, 5).mem(0x300, &[0b1000_0000]).i(0x300).run(0xDF11);
+        assert!(pixel(&cpu, 10, 5));
+        assert_eq!(cpu.v()[0xF], 0);
+    }
+}
@@ -0,0 +1,428 @@
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::cpu::CPU;
+use crate::disasm::{disassemble, disassemble_range};
+use crate::watch::{Access, Target, Watchpoint};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register {
+    V(u8),
+    I,
+    Sp,
+    Dt,
+    St
+}
+
+impl Register {
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.to_ascii_lowercase();
+        match s.as_str() {
+            "i" => Some(Register::I),
+            "sp" => Some(Register::Sp),
+            "dt" => Some(Register::Dt),
+            "st" => Some(Register::St),
+            _ => {
+                let x = u8::from_str_radix(s.strip_prefix('v')?, 16).ok()?;
+                if x < 16 { Some(Register::V(x)) } else { None }
+            }
+        }
+    }
+
+    fn read(&self, cpu: &CPU) -> u16 {
+        match *self {
+            Register::V(x) => cpu.v()[x as usize] as u16,
+            Register::I => cpu.i(),
+            Register::Sp => cpu.sp() as u16,
+            Register::Dt => cpu.dt() as u16,
+            Register::St => cpu.st() as u16
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cmp {
+    Eq, Ne, Lt, Le, Gt, Ge
+}
+
+impl Cmp {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "==" => Cmp::Eq,
+            "!=" => Cmp::Ne,
+            "<" => Cmp::Lt,
+            "<=" => Cmp::Le,
+            ">" => Cmp::Gt,
+            ">=" => Cmp::Ge,
+            _ => return None
+        })
+    }
+
+    fn eval(&self, a: u16, b: u16) -> bool {
+        match self {
+            Cmp::Eq => a == b,
+            Cmp::Ne => a != b,
+            Cmp::Lt => a < b,
+            Cmp::Le => a <= b,
+            Cmp::Gt => a > b,
+            Cmp::Ge => a >= b
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Condition {
+    pub reg: Register,
+    pub cmp: Cmp,
+    pub value: u16
+}
+
+// A breakpoint triggers before the instruction at `addr` executes, if `cond` holds.
+// Breakpoints without an address are checked before every instruction.
+#[derive(Clone, Copy, Debug)]
+pub struct Breakpoint {
+    pub addr: Option<u16>,
+    pub cond: Option<Condition>
+}
+
+impl Breakpoint {
+    fn hit(&self, cpu: &CPU) -> bool {
+        self.addr.is_none_or(|a| a == cpu.pc())
+            && self.cond.is_none_or(|c| c.cmp.eval(c.reg.read(cpu), c.value))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Paused,
+    Running,
+    // Execute this many more instructions, then pause.
+    Step(u32),
+    // Run until PC returns to `ret` at the same stack depth.
+    StepOver { ret: u16, sp: u8 },
+    // Run until the current subroutine returns.
+    StepOut { sp: u8 },
+    RunTo(u16)
+}
+
+pub struct Debugger {
+    pub breakpoints: Vec<Breakpoint>,
+    pub quit: bool,
+    mode: Mode,
+    // Set when resuming so the breakpoint at the current PC doesn't immediately re-trigger.
+    resumed: bool,
+    commands: Receiver<String>
+}
+
+impl Debugger {
+    pub fn attach() -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        // Reading stdin blocks, so it gets its own thread to keep the SDL event loop responsive.
+        thread::spawn(move || {
+            for line in io::stdin().lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        println!("Debugger attached, type `help` for a list of commands.");
+        prompt();
+
+        Debugger {
+            breakpoints: Vec::new(),
+            quit: false,
+            mode: Mode::Paused,
+            resumed: false,
+            commands: rx
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    pub fn pause(&mut self, cpu: &CPU) {
+        self.mode = Mode::Paused;
+        report(cpu);
+    }
+
+    // Pauses outside of the debugger's own stop conditions, e.g. on a CPU fault.
+    pub fn halt(&mut self, cpu: &CPU, reason: &str) {
+        println!("{reason}");
+        self.pause(cpu);
+        prompt();
+    }
+
+    // Handles any pending commands and decides whether the CPU may execute its next instruction.
+    pub fn should_tick(&mut self, cpu: &mut CPU) -> bool {
+        while let Ok(line) = self.commands.try_recv() {
+            self.command(&line, cpu);
+            if self.mode == Mode::Paused && !self.quit {
+                prompt();
+            }
+        }
+
+        if self.mode == Mode::Paused {
+            return false;
+        }
+
+        let resumed = std::mem::take(&mut self.resumed);
+        let stop = match self.mode {
+            Mode::Step(0) => true,
+            Mode::Step(n) => {
+                self.mode = Mode::Step(n - 1);
+                false
+            }
+            Mode::StepOver { ret, sp } => cpu.pc() == ret && cpu.sp() == sp,
+            Mode::StepOut { sp } => cpu.sp() < sp,
+            Mode::RunTo(addr) => cpu.pc() == addr,
+            Mode::Running | Mode::Paused => false
+        };
+
+        if stop {
+            self.pause(cpu);
+            prompt();
+            return false;
+        }
+
+        if !resumed {
+            if let Some(i) = self.breakpoints.iter().position(|b| b.hit(cpu)) {
+                println!("Breakpoint {i} hit.");
+                self.pause(cpu);
+                prompt();
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Halts execution if the last instruction triggered a watchpoint.
+    pub fn after_tick(&mut self, cpu: &mut CPU) {
+        let Some(hit) = cpu.take_watch_hit() else { return };
+
+        let kind = if hit.write { "write" } else { "read" };
+        println!("Watchpoint {} hit, {kind} of {} by 0x{:03x}: {:04x}  {}", hit.index, hit.target, hit.pc, hit.op, disassemble(hit.op));
+        if hit.write {
+            println!("Old: 0x{:x}  New: 0x{:x}", hit.old, hit.new);
+        }
+        else {
+            println!("Value: 0x{:x}", hit.new);
+        }
+        self.pause(cpu);
+        prompt();
+    }
+
+    fn resume(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.resumed = true;
+    }
+
+    fn command(&mut self, line: &str, cpu: &mut CPU) {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let Some((&cmd, args)) = args.split_first() else { return };
+
+        match cmd {
+            "c" | "continue" => self.resume(Mode::Running),
+            "s" | "step" => {
+                let n = args.first().and_then(|a| parse_num(a)).unwrap_or(1);
+                self.resume(Mode::Step(n as u32));
+            }
+            "n" | "next" => {
+                // Only CALL needs stepping over, anything else is a regular single step.
+                if cpu.next_op() >> 12 == 0x2 {
+                    self.resume(Mode::StepOver { ret: cpu.pc() + 2, sp: cpu.sp() });
+                }
+                else {
+                    self.resume(Mode::Step(1));
+                }
+            }
+            "f" | "finish" => {
+                if cpu.sp() == 0 {
+                    println!("Not inside a subroutine.");
+                    return;
+                }
+                self.resume(Mode::StepOut { sp: cpu.sp() });
+            }
+            "u" | "until" => match args.first().and_then(|a| parse_num(a)) {
+                Some(addr) => self.resume(Mode::RunTo(addr)),
+                None => println!("Usage: until <addr>")
+            },
+            "p" | "pause" => {
+                if self.mode != Mode::Paused {
+                    self.pause(cpu);
+                }
+            }
+            "b" | "break" => match parse_breakpoint(args) {
+                Some(bp) => {
+                    self.breakpoints.push(bp);
+                    println!("Breakpoint {} set.", self.breakpoints.len() - 1);
+                }
+                None => println!("Usage: break [addr] [if <reg> <op> <value>]")
+            },
+            "d" | "delete" => match args.first().and_then(|a| parse_num(a)) {
+                Some(i) if (i as usize) < self.breakpoints.len() => {
+                    self.breakpoints.remove(i as usize);
+                }
+                Some(_) => println!("No such breakpoint."),
+                None => self.breakpoints.clear()
+            },
+            "bl" | "breakpoints" => {
+                for (i, bp) in self.breakpoints.iter().enumerate() {
+                    print!("{i}:");
+                    if let Some(addr) = bp.addr {
+                        print!(" 0x{addr:03x}");
+                    }
+                    if let Some(c) = bp.cond {
+                        print!(" if {:?} {:?} 0x{:x}", c.reg, c.cmp, c.value);
+                    }
+                    println!();
+                }
+            }
+            "w" | "watch" => match parse_watchpoint(args) {
+                Some(wp) => {
+                    cpu.watchpoints.push(wp);
+                    println!("Watchpoint {} set.", cpu.watchpoints.len() - 1);
+                }
+                None => println!("Usage: watch <addr>[-<end>] | i | v<x> [r|w|rw]")
+            },
+            "uw" | "unwatch" => match args.first().and_then(|a| parse_num(a)) {
+                Some(i) if (i as usize) < cpu.watchpoints.len() => {
+                    cpu.watchpoints.remove(i as usize);
+                }
+                Some(_) => println!("No such watchpoint."),
+                None => cpu.watchpoints.clear()
+            },
+            "wl" | "watches" => {
+                for (i, wp) in cpu.watchpoints.iter().enumerate() {
+                    println!("{i}: {} {:?}", wp.target, wp.access);
+                }
+            }
+            "r" | "regs" => print_regs(cpu),
+            "m" | "mem" => {
+                let addr = args.first().and_then(|a| parse_num(a)).unwrap_or(cpu.i());
+                let len = args.get(1).and_then(|a| parse_num(a)).unwrap_or(16);
+                print_mem(cpu, addr, len);
+            }
+            "l" | "dis" => {
+                let addr = args.first().and_then(|a| parse_num(a)).unwrap_or(cpu.pc());
+                let count = args.get(1).and_then(|a| parse_num(a)).unwrap_or(8);
+                for (a, op, text) in disassemble_range(&cpu.memory, addr, count as usize) {
+                    let marker = if a == cpu.pc() { ">" } else { " " };
+                    println!("{marker} 0x{a:03x}: {op:04x}  {text}");
+                }
+            }
+            "q" | "quit" => self.quit = true,
+            "h" | "help" => print_help(),
+            _ => println!("Unknown command `{cmd}`, type `help` for a list of commands.")
+        }
+    }
+}
+
+// Parses `0x2A4`, `0x2A4 if v3 == 0x10` or `if v3 == 0x10`.
+fn parse_breakpoint(args: &[&str]) -> Option<Breakpoint> {
+    let (addr, rest) = match args.first() {
+        Some(&"if") => (None, args),
+        Some(a) => (Some(parse_num(a)?), &args[1..]),
+        None => return None
+    };
+
+    let cond = match rest {
+        [] => None,
+        ["if", reg, cmp, value] => Some(Condition {
+            reg: Register::parse(reg)?,
+            cmp: Cmp::parse(cmp)?,
+            value: parse_num(value)?
+        }),
+        _ => return None
+    };
+
+    Some(Breakpoint { addr, cond })
+}
+
+// Parses `0x300`, `0x300-0x31f`, `i` or `v3`, optionally followed by the access kind (default write).
+fn parse_watchpoint(args: &[&str]) -> Option<Watchpoint> {
+    let (target, access) = match args {
+        [target] => (*target, Access::Write),
+        [target, access] => (*target, Access::parse(access)?),
+        _ => return None
+    };
+
+    let target = match Register::parse(target) {
+        Some(Register::I) => Target::I,
+        Some(Register::V(x)) => Target::V(x),
+        Some(_) => return None,
+        None => match target.split_once('-') {
+            Some((start, end)) => Target::Memory { start: parse_num(start)?, end: parse_num(end)? },
+            None => {
+                let addr = parse_num(target)?;
+                Target::Memory { start: addr, end: addr }
+            }
+        }
+    };
+
+    Some(Watchpoint { target, access })
+}
+
+pub fn parse_num(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok()
+    }
+}
+
+fn prompt() {
+    print!("(chip8) ");
+    let _ = io::stdout().flush();
+}
+
+fn report(cpu: &CPU) {
+    println!("Paused at 0x{:03x}: {:04x}  {}", cpu.pc(), cpu.next_op(), disassemble(cpu.next_op()));
+}
+
+fn print_regs(cpu: &CPU) {
+    for (x, v) in cpu.v().iter().enumerate() {
+        print!("V{x:X}: {v:02x}  ");
+        if x % 8 == 7 {
+            println!();
+        }
+    }
+    println!("PC: {:03x}  I: {:03x}  SP: {:x}  DT: {:02x}  ST: {:02x}", cpu.pc(), cpu.i(), cpu.sp(), cpu.dt(), cpu.st());
+    println!("Stack: {:03x?}", cpu.stack());
+}
+
+fn print_mem(cpu: &CPU, addr: u16, len: u16) {
+    let start = addr as usize;
+    let end = (start + len as usize).min(cpu.memory.len());
+    for (row, chunk) in cpu.memory[start.min(end)..end].chunks(16).enumerate() {
+        let bytes: Vec<String> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+        println!("0x{:03x}: {}", start + row * 16, bytes.join(" "));
+    }
+}
+
+fn print_help() {
+    println!("\
+c, continue                  Resume execution
+s, step [n]                  Execute n instructions (default 1)
+n, next                      Step over CALL instructions
+f, finish                    Run until the current subroutine returns
+u, until <addr>              Run until PC reaches addr
+p, pause                     Pause execution
+b, break [addr] [if <reg> <op> <value>]
+                             Set a breakpoint, reg is one of V0-VF, I, SP, DT, ST
+                             and op is one of == != < <= > >=
+d, delete [n]                Delete breakpoint n, or all breakpoints
+bl, breakpoints              List breakpoints
+w, watch <target> [r|w|rw]   Halt on access to target, one of addr, addr-end, I or V0-VF
+                             (default w)
+uw, unwatch [n]              Delete watchpoint n, or all watchpoints
+wl, watches                  List watchpoints
+r, regs                      Print registers
+m, mem [addr] [len]          Dump memory (defaults to I and 16 bytes)
+l, dis [addr] [count]        Disassemble (defaults to PC)
+q, quit                      Exit the emulator");
+}
@@ -0,0 +1,73 @@
+use crate::instruction::Instruction;
+
+// Mnemonics follow Cowgod's technical reference.
+pub fn disassemble(op: u16) -> String {
+    let args = Instruction::new(op);
+    let (x, y, n, kk, nnn) = (args.x, args.y, args.n, args.kk, args.nnn);
+
+    match op >> 12 {
+        0x0 => match op {
+            0x00E0 => "CLS".to_string(),
+            0x00EE => "RET".to_string(),
+            _ => format!("SYS 0x{nnn:03x}")
+        },
+        0x1 => format!("JP 0x{nnn:03x}"),
+        0x2 => format!("CALL 0x{nnn:03x}"),
+        0x3 => format!("SE V{x:X}, 0x{kk:02x}"),
+        0x4 => format!("SNE V{x:X}, 0x{kk:02x}"),
+        0x5 if n == 0 => format!("SE V{x:X}, V{y:X}"),
+        0x6 => format!("LD V{x:X}, 0x{kk:02x}"),
+        0x7 => format!("ADD V{x:X}, 0x{kk:02x}"),
+        0x8 => match n {
+            0x0 => format!("LD V{x:X}, V{y:X}"),
+            0x1 => format!("OR V{x:X}, V{y:X}"),
+            0x2 => format!("AND V{x:X}, V{y:X}"),
+            0x3 => format!("XOR V{x:X}, V{y:X}"),
+            0x4 => format!("ADD V{x:X}, V{y:X}"),
+            0x5 => format!("SUB V{x:X}, V{y:X}"),
+            0x6 => format!("SHR V{x:X}, V{y:X}"),
+            0x7 => format!("SUBN V{x:X}, V{y:X}"),
+            0xE => format!("SHL V{x:X}, V{y:X}"),
+            _ => data(op)
+        },
+        0x9 if n == 0 => format!("SNE V{x:X}, V{y:X}"),
+        0xA => format!("LD I, 0x{nnn:03x}"),
+        0xB => format!("JP V0, 0x{nnn:03x}"),
+        0xC => format!("RND V{x:X}, 0x{kk:02x}"),
+        0xD => format!("DRW V{x:X}, V{y:X}, {n}"),
+        0xE => match kk {
+            0x9E => format!("SKP V{x:X}"),
+            0xA1 => format!("SKNP V{x:X}"),
+            _ => data(op)
+        },
+        0xF => match kk {
+            0x07 => format!("LD V{x:X}, DT"),
+            0x0A => format!("LD V{x:X}, K"),
+            0x15 => format!("LD DT, V{x:X}"),
+            0x18 => format!("LD ST, V{x:X}"),
+            0x1E => format!("ADD I, V{x:X}"),
+            0x29 => format!("LD F, V{x:X}"),
+            0x33 => format!("LD B, V{x:X}"),
+            0x55 => format!("LD [I], V{x:X}"),
+            0x65 => format!("LD V{x:X}, [I]"),
+            _ => data(op)
+        },
+        _ => data(op)
+    }
+}
+
+// Disassembles `count` instructions starting at `addr`, stopping at the end of memory.
+pub fn disassemble_range(memory: &[u8], addr: u16, count: usize) -> Vec<(u16, u16, String)> {
+    (0..count)
+        .map(|i| addr as usize + i * 2)
+        .take_while(|a| a + 1 < memory.len())
+        .map(|a| {
+            let op = ((memory[a] as u16) << 8) | memory[a + 1] as u16;
+            (a as u16, op, disassemble(op))
+        })
+        .collect()
+}
+
+fn data(op: u16) -> String {
+    format!("DW 0x{op:04x}")
+}
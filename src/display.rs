@@ -1,7 +1,20 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
 use sdl2::Sdl;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::{Texture, WindowCanvas};
+use sdl2::video::FullscreenType;
+
+use crate::cpu::CPU;
+use crate::filter::{Filter, FilterChain};
+use crate::overlay::{self, PANEL_WIDTH, PANEL_HEIGHT};
+use crate::palette::Palette;
+use crate::persistence::{Persistence, PersistenceMode};
+use crate::png;
 
 pub const DISPLAY_WIDTH: usize = 64;
 pub const DISPLAY_HEIGHT: usize = 32;
@@ -9,8 +22,15 @@ pub const DISPLAY_SIZE: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
 
 pub struct Display {
     canvas: WindowCanvas,
-    scale: usize,
-    pub buffer: [bool; DISPLAY_SIZE as usize],
+    // Streaming texture at framebuffer resolution, uploaded once per draw and scaled by the GPU.
+    texture: Texture,
+    overlay: bool,
+    // Only scale the game by whole numbers, leaving a larger border.
+    integer_scaling: bool,
+    filter: Option<FilterChain>,
+    pub palette: Palette,
+    pub persistence: Persistence,
+    pub buffer: [bool; DISPLAY_SIZE],
     pub redraw: bool
 }    
 
@@ -21,39 +41,157 @@ impl Display {
 }    
 
 impl Display {
-    pub fn new(sdl: &Sdl, scale: usize) -> Self {
+    // With `overlay` the window is widened to fit the debugger panels to the right of the game.
+    // The window starts at `scale` times the display size and can be resized freely.
+    pub fn new(sdl: &Sdl, scale: usize, palette: Palette, overlay: bool, integer_scaling: bool) -> Self {
+        let (width, height) = if overlay {
+            (DISPLAY_WIDTH * scale + PANEL_WIDTH, (DISPLAY_HEIGHT * scale).max(PANEL_HEIGHT))
+        } else {
+            (DISPLAY_WIDTH * scale, DISPLAY_HEIGHT * scale)
+        };
+
         let video = sdl.video().unwrap();
         let window = video.window(
             "CHIP-8",
-            (DISPLAY_WIDTH * scale) as u32, 
-            (DISPLAY_HEIGHT * scale) as u32
+            width as u32,
+            height as u32
         )
             .position_centered()
+            .resizable()
             .build()
             .unwrap();
 
             let mut canvas = window.into_canvas().build().unwrap();
-            canvas.set_draw_color(Color::BLACK);
-            canvas.set_scale(scale as f32, scale as f32).unwrap();
+            canvas.set_draw_color(palette.background());
             canvas.clear();
             canvas.present();
 
+        let texture = canvas.texture_creator()
+            .create_texture_streaming(PixelFormatEnum::RGB24, DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32)
+            .unwrap();
+
         Display {
-            canvas, scale, buffer: [false; DISPLAY_SIZE], redraw: false
+            canvas, texture, overlay, integer_scaling, filter: None, palette,
+            persistence: Persistence::new(PersistenceMode::Off, 0.0),
+            buffer: [false; DISPLAY_SIZE], redraw: false
         }
     }
 
-    pub fn draw(&mut self, pixels: &[bool; DISPLAY_SIZE]) {
-        for (i, b) in pixels.iter().enumerate() {
-            let x = (i % DISPLAY_WIDTH) as i32;
-            let y = (i / DISPLAY_WIDTH) as i32;
-        //     let rect = Rect::new(x, y, self.scale as u32, self.scale as u32);
-            self.canvas.set_draw_color(
-                if *b { Color::WHITE } else { Color::BLACK }
-            );
-            let rect = Rect::new(x, y, 1, 1);
-            let _ = self.canvas.fill_rect(rect);
+    // Enables software post-processing, the texture is enlarged to the filtered resolution.
+    pub fn set_filters(&mut self, filters: &[Filter], scale: usize) {
+        let chain = (!filters.is_empty()).then(|| FilterChain::new(filters, scale));
+        let (width, height) = chain.as_ref().map_or((DISPLAY_WIDTH, DISPLAY_HEIGHT), |c| (c.width(), c.height()));
+        let query = self.texture.query();
+        if (query.width, query.height) != (width as u32, height as u32) {
+            let texture = self.canvas.texture_creator()
+                .create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+                .unwrap();
+            // With sdl2's unsafe_textures feature a texture isn't freed on drop and isn't tied to
+            // the renderer's lifetime, so it has to be destroyed by hand.
+            // SAFETY: the old texture is only ever referenced through `self.texture`, which now
+            // holds the new one, so nothing uses it after it's destroyed. It was created by
+            // `self.canvas`, which is still alive here.
+            unsafe { std::mem::replace(&mut self.texture, texture).destroy() };
         }
+        self.filter = chain;
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        let _ = self.canvas.window_mut().set_title(title);
+    }
+
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let mode = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off
+        };
+        let _ = window.set_fullscreen(mode);
+    }
+
+    pub fn draw(&mut self, pixels: &[bool; DISPLAY_SIZE]) {
+        self.draw_pixels(pixels);
+        self.canvas.present();
+    }
+
+    // Draws the game along with the debugger overlay panels.
+    pub fn draw_overlay(&mut self, cpu: &CPU) {
+        self.draw_pixels(&cpu.vbuffer);
+        let (width, _) = self.game_area();
+        overlay::draw(&mut self.canvas, width as i32, 0, cpu);
         self.canvas.present();
     }
-}   
\ No newline at end of file
+
+    fn draw_pixels(&mut self, pixels: &[bool; DISPLAY_SIZE]) {
+        let (bg, fg) = (self.palette.background(), self.palette.foreground());
+        let brightness = self.persistence.apply(pixels);
+        let mut colors = [Color::BLACK; DISPLAY_SIZE];
+        for (c, b) in colors.iter_mut().zip(brightness) {
+            *c = blend(bg, fg, *b);
+        }
+
+        let filter = &mut self.filter;
+        let _ = self.texture.with_lock(None, |buffer, pitch| {
+            if let Some(filter) = filter {
+                filter.render(&colors, buffer, pitch);
+                return;
+            }
+            for (i, c) in colors.iter().enumerate() {
+                let offset = (i / DISPLAY_WIDTH) * pitch + (i % DISPLAY_WIDTH) * 3;
+                buffer[offset..offset + 3].copy_from_slice(&[c.r, c.g, c.b]);
+            }
+        });
+
+        // Clear first so the letterbox borders don't keep stale pixels after a resize.
+        self.canvas.set_draw_color(bg);
+        self.canvas.clear();
+        let _ = self.canvas.copy(&self.texture, None, self.game_rect());
+    }
+
+    // Size of the part of the window the game is drawn in, to the left of the overlay panels.
+    fn game_area(&self) -> (u32, u32) {
+        let (width, height) = self.canvas.output_size().unwrap_or((0, 0));
+        if self.overlay {
+            (width.saturating_sub(PANEL_WIDTH as u32), height)
+        }
+        else {
+            (width, height)
+        }
+    }
+
+    // Largest rect with the framebuffer's aspect ratio that fits the game area, centered.
+    // Based on the texture size, so it adapts to whatever resolution the framebuffer has.
+    fn game_rect(&self) -> Rect {
+        let (area_w, area_h) = self.game_area();
+        let query = self.texture.query();
+        let (tex_w, tex_h) = (query.width, query.height);
+
+        let mut scale = (area_w as f32 / tex_w as f32).min(area_h as f32 / tex_h as f32);
+        if self.integer_scaling {
+            scale = scale.floor().max(1.0);
+        }
+        let (w, h) = ((tex_w as f32 * scale) as u32, (tex_h as f32 * scale) as u32);
+        Rect::new((area_w as i32 - w as i32) / 2, (area_h as i32 - h as i32) / 2, w.max(1), h.max(1))
+    }
+}   
+
+fn blend(a: Color, b: Color, t: f32) -> Color {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color::RGB(mix(a.r, b.r), mix(a.g, b.g), mix(a.b, b.b))
+}
+
+// Writes the framebuffer to a PNG, each pixel scaled up to `scale` x `scale`.
+// Doesn't need a window, so it also works headless.
+pub fn screenshot(path: &Path, pixels: &[bool; DISPLAY_SIZE], scale: usize, palette: &Palette) -> io::Result<()> {
+    let (width, height) = (DISPLAY_WIDTH * scale, DISPLAY_HEIGHT * scale);
+    let png = png::encode_indexed(width as u32, height as u32, &palette.to_rgb(2), &scale_pixels(pixels, scale));
+    fs::write(path, png)
+}
+
+// Upscales the framebuffer to one palette index (0 off, 1 on) per output pixel.
+pub fn scale_pixels(pixels: &[bool; DISPLAY_SIZE], scale: usize) -> Vec<u8> {
+    let width = DISPLAY_WIDTH * scale;
+    (0..width * DISPLAY_HEIGHT * scale)
+        .map(|i| pixels[(i / width / scale) * DISPLAY_WIDTH + (i % width) / scale] as u8)
+        .collect()
+}
\ No newline at end of file
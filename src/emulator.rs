@@ -0,0 +1,139 @@
+use std::thread;
+use std::time::Duration;
+
+use crate::audio::{AudioRecorder, Beeper};
+use crate::cpu::CPU;
+use crate::debugger::Debugger;
+use crate::gdb::GdbStub;
+use crate::recording::GifRecorder;
+use crate::trace::{Snapshot, Tracer};
+
+// Runs the CPU one 60Hz frame at a time along with everything hooked into execution.
+// Independent of the window so the same loop drives both the SDL frontend and headless runs.
+pub struct Emulator {
+    pub chip: CPU,
+    pub ticks_per_frame: u8,
+    pub tick_delay: u64,
+    pub debugger: Option<Debugger>,
+    pub gdb: Option<GdbStub>,
+    pub tracer: Option<Tracer>,
+    pub beeper: Option<Beeper>,
+    pub recorder: Option<AudioRecorder>,
+    pub video: Option<GifRecorder>,
+    // Instructions executed since the timers last ticked.
+    cycles: u32
+}
+
+impl Emulator {
+    pub fn new(chip: CPU, ticks_per_frame: u8, tick_delay: u64) -> Self {
+        Emulator {
+            chip,
+            ticks_per_frame,
+            tick_delay,
+            debugger: None,
+            gdb: None,
+            tracer: None,
+            beeper: None,
+            recorder: None,
+            video: None,
+            cycles: 0
+        }
+    }
+
+    // Executes up to `ticks_per_frame` instructions, fewer if a debugger halts execution.
+    // Returns false once the emulator should exit.
+    pub fn run_frame(&mut self) -> bool {
+        for _ in 0..self.ticks_per_frame {
+            let mut run = match self.debugger.as_mut() {
+                Some(dbg) => dbg.should_tick(&mut self.chip),
+                None => true
+            };
+            if let Some(stub) = self.gdb.as_mut() {
+                run &= stub.should_tick(&mut self.chip);
+            }
+            if self.debugger.as_ref().is_some_and(|dbg| dbg.quit) || self.gdb.as_ref().is_some_and(|stub| stub.quit) {
+                return false;
+            }
+            if !run {
+                break;
+            }
+
+            let before = self.tracer.as_ref().map(|_| Snapshot::capture(&self.chip));
+            let result = self.chip.tick();
+            if let (Some(tracer), Some(before)) = (self.tracer.as_mut(), before) {
+                tracer.record(before, &self.chip);
+            }
+
+            if let Err(fault) = result {
+                if let Some(tracer) = self.tracer.as_ref() {
+                    tracer.dump_history();
+                }
+                match self.debugger.as_mut() {
+                    Some(dbg) => dbg.halt(&self.chip, &fault.to_string()),
+                    None => {
+                        println!("{fault}");
+                        return false;
+                    }
+                }
+            }
+
+            if let Some(dbg) = self.debugger.as_mut() {
+                dbg.after_tick(&mut self.chip);
+            }
+            if let Some(stub) = self.gdb.as_mut() {
+                stub.after_tick();
+            }
+
+            // Timers advance with emulated time, so they stay in step with the program while paused or stepping.
+            self.cycles += 1;
+            if self.cycles >= self.ticks_per_frame as u32 {
+                self.cycles = 0;
+                self.timer_frame();
+            }
+
+            if self.tick_delay > 0 {
+                thread::sleep(Duration::from_millis(self.tick_delay));
+            }
+        }
+        true
+    }
+
+    fn timer_frame(&mut self) {
+        // The frame during which ST reaches zero is the last one that sounds.
+        let beep = self.chip.st() > 0;
+        self.chip.tick_timers();
+
+        if let Some(beeper) = self.beeper.as_mut() {
+            beeper.frame(beep);
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.frame(beep) {
+                println!("Failed to record audio, stopping recording: {e}");
+                self.recorder = None;
+            }
+        }
+        if let Some(video) = self.video.as_mut() {
+            if let Err(e) = video.frame(&self.chip.vbuffer) {
+                println!("Failed to record video, stopping recording: {e}");
+                self.video = None;
+            }
+        }
+    }
+
+    // Flushes any output files, call once before exiting.
+    pub fn finish(&mut self) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.flush();
+        }
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.finish() {
+                println!("Failed to finish audio recording: {e}");
+            }
+        }
+        if let Some(video) = self.video.as_mut() {
+            if let Err(e) = video.finish() {
+                println!("Failed to finish video recording: {e}");
+            }
+        }
+    }
+}
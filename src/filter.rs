@@ -0,0 +1,169 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use sdl2::pixels::Color;
+
+use crate::display::{DISPLAY_HEIGHT, DISPLAY_SIZE, DISPLAY_WIDTH};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    /// Darken the gap between rows of pixels.
+    Scanlines,
+    /// Darken the gap between every pixel.
+    Grid,
+    /// Let lit pixels glow onto their neighbours.
+    Bloom,
+    /// Bend the image like the glass of a CRT.
+    Curvature,
+    /// Scanlines, bloom and curvature together.
+    Crt
+}
+
+const SCANLINE_BRIGHTNESS: f32 = 0.55;
+const GRID_BRIGHTNESS: f32 = 0.7;
+const BLOOM_STRENGTH: f32 = 0.45;
+const CURVATURE: f32 = 0.06;
+
+// Post-processing done on the CPU so it works without shader support.
+// The framebuffer is upscaled to `scale` output pixels per CHIP-8 pixel, then filtered.
+pub struct FilterChain {
+    scanlines: bool,
+    grid: bool,
+    bloom: bool,
+    curvature: bool,
+    scale: usize,
+    image: Vec<[f32; 3]>,
+    glow: Vec<[f32; 3]>,
+    scratch: Vec<[f32; 3]>
+}
+
+impl FilterChain {
+    pub fn new(filters: &[Filter], scale: usize) -> Self {
+        let has = |f: Filter| filters.contains(&f);
+        // Filters need a few output pixels per CHIP-8 pixel, and the cost grows with the square of the scale.
+        let scale = scale.clamp(3, 8);
+        let size = DISPLAY_SIZE * scale * scale;
+        FilterChain {
+            scanlines: has(Filter::Scanlines) || has(Filter::Crt),
+            grid: has(Filter::Grid),
+            bloom: has(Filter::Bloom) || has(Filter::Crt),
+            curvature: has(Filter::Curvature) || has(Filter::Crt),
+            scale,
+            image: vec![[0.0; 3]; size],
+            glow: vec![[0.0; 3]; size],
+            scratch: vec![[0.0; 3]; size]
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        DISPLAY_WIDTH * self.scale
+    }
+
+    pub fn height(&self) -> usize {
+        DISPLAY_HEIGHT * self.scale
+    }
+
+    // Upscales `colors`, one per framebuffer pixel, runs the enabled filters and
+    // writes the result as RGB24 rows `pitch` bytes apart.
+    pub fn render(&mut self, colors: &[Color; DISPLAY_SIZE], out: &mut [u8], pitch: usize) {
+        let (width, scale) = (self.width(), self.scale);
+        for (i, p) in self.image.iter_mut().enumerate() {
+            let c = colors[(i / width / scale) * DISPLAY_WIDTH + (i % width) / scale];
+            *p = [c.r as f32 / 255.0, c.g as f32 / 255.0, c.b as f32 / 255.0];
+        }
+
+        // The glow is taken before the gaps are darkened so it bleeds into them.
+        if self.bloom {
+            self.blur();
+        }
+        if self.scanlines || self.grid {
+            self.darken_gaps();
+        }
+        if self.bloom {
+            for (p, g) in self.image.iter_mut().zip(&self.glow) {
+                for c in 0..3 {
+                    p[c] += g[c] * BLOOM_STRENGTH;
+                }
+            }
+        }
+        if self.curvature {
+            self.curve();
+        }
+
+        for (i, p) in self.image.iter().enumerate() {
+            let offset = (i / width) * pitch + (i % width) * 3;
+            for c in 0..3 {
+                out[offset + c] = (p[c].clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+    }
+
+    fn darken_gaps(&mut self) {
+        let (width, scale) = (self.width(), self.scale);
+        // Bottom third of each row of CHIP-8 pixels, at least one output row.
+        let scanline = scale - (scale / 3).max(1);
+        for (i, p) in self.image.iter_mut().enumerate() {
+            let (x, y) = (i % width % scale, i / width % scale);
+            let mut factor = 1.0;
+            if self.scanlines && y >= scanline {
+                factor *= SCANLINE_BRIGHTNESS;
+            }
+            if self.grid && (x == scale - 1 || y == scale - 1) {
+                factor *= GRID_BRIGHTNESS;
+            }
+            for c in p.iter_mut() {
+                *c *= factor;
+            }
+        }
+    }
+
+    // Separable box blur of `image` into `glow`, about one CHIP-8 pixel wide.
+    fn blur(&mut self) {
+        let (width, height) = (self.width(), self.height());
+        let radius = (self.scale / 2) as isize;
+        box_blur(&self.image, &mut self.scratch, width, height, radius, 1);
+        box_blur(&self.scratch, &mut self.glow, width, height, radius, width);
+    }
+
+    // Barrel distortion, pixels bent past the edge of the tube are black.
+    fn curve(&mut self) {
+        let (width, height) = (self.width(), self.height());
+        for (i, p) in self.scratch.iter_mut().enumerate() {
+            let x = ((i % width) as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+            let y = ((i / width) as f32 + 0.5) / height as f32 * 2.0 - 1.0;
+            let sx = x * (1.0 + CURVATURE * y * y);
+            let sy = y * (1.0 + CURVATURE * x * x);
+            *p = if sx.abs() > 1.0 || sy.abs() > 1.0 {
+                [0.0; 3]
+            }
+            else {
+                let sx = (((sx + 1.0) / 2.0 * width as f32) as usize).min(width - 1);
+                let sy = (((sy + 1.0) / 2.0 * height as f32) as usize).min(height - 1);
+                self.image[sy * width + sx]
+            };
+        }
+        std::mem::swap(&mut self.image, &mut self.scratch);
+    }
+}
+
+// Averages each pixel with its neighbours within `radius` along one axis,
+// `stride` is 1 for rows and the image width for columns.
+fn box_blur(src: &[[f32; 3]], dst: &mut [[f32; 3]], width: usize, height: usize, radius: isize, stride: usize) {
+    let len = if stride == 1 { width } else { height } as isize;
+    for (i, d) in dst.iter_mut().enumerate() {
+        let pos = if stride == 1 { i % width } else { i / width } as isize;
+        let mut sum = [0.0; 3];
+        for offset in -radius..=radius {
+            let p = pos + offset;
+            if p < 0 || p >= len {
+                continue;
+            }
+            let s = src[(i as isize + offset * stride as isize) as usize];
+            for c in 0..3 {
+                sum[c] += s[c];
+            }
+        }
+        let n = (radius * 2 + 1) as f32;
+        *d = [sum[0] / n, sum[1] / n, sum[2] / n];
+    }
+}
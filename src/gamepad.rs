@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use sdl2::{GameControllerSubsystem, Sdl};
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use serde::Deserialize;
+
+use crate::input::{parse_chip_key, KeymapFile, LAYOUT};
+
+pub const DEFAULT_DEADZONE: i16 = 8000;
+
+// A controller input that can press a CHIP-8 key: a button, or a stick or trigger pushed one way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PadInput {
+    Button(Button),
+    Axis(Axis, bool)
+}
+
+// Names as taken by `parse_pad_input`.
+impl fmt::Display for PadInput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PadInput::Button(button) => write!(f, "{}", button.string()),
+            PadInput::Axis(axis, positive) => write!(f, "{}{}", axis.string(), if *positive { '+' } else { '-' })
+        }
+    }
+}
+
+// Inputs bound to each CHIP-8 key, any of them on any connected controller presses it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PadMap {
+    bindings: [Vec<PadInput>; 16],
+    // How far a stick has to be pushed from the centre, out of 32767.
+    pub deadzone: i16
+}
+
+impl Default for PadMap {
+    // D-pad and left stick on the 2/4/6/8 arrows most games use, face buttons on the keys around them.
+    fn default() -> Self {
+        use PadInput::{Axis as A, Button as B};
+        let mut map = PadMap { bindings: Default::default(), deadzone: DEFAULT_DEADZONE };
+        map.bind(0x2, &[B(Button::DPadUp), A(Axis::LeftY, false)]);
+        map.bind(0x8, &[B(Button::DPadDown), A(Axis::LeftY, true)]);
+        map.bind(0x4, &[B(Button::DPadLeft), A(Axis::LeftX, false)]);
+        map.bind(0x6, &[B(Button::DPadRight), A(Axis::LeftX, true)]);
+        map.bind(0x5, &[B(Button::A)]);
+        map.bind(0x0, &[B(Button::B)]);
+        map.bind(0x7, &[B(Button::X)]);
+        map.bind(0x9, &[B(Button::Y)]);
+        map.bind(0x1, &[B(Button::Back)]);
+        map.bind(0xF, &[B(Button::Start)]);
+        map
+    }
+}
+
+impl PadMap {
+    // Replaces the bindings of `key`.
+    pub fn bind(&mut self, key: u8, inputs: &[PadInput]) {
+        self.bindings[key as usize] = inputs.to_vec();
+    }
+
+    // Adds `input` to the inputs that press `key`.
+    pub fn add(&mut self, key: u8, input: PadInput) {
+        let inputs = &mut self.bindings[key as usize & 0xF];
+        if !inputs.contains(&input) {
+            inputs.push(input);
+        }
+    }
+
+    // The bindings in keymap file syntax, as a `table` with the deadzone and a keys subtable.
+    pub fn to_toml(&self, table: &str) -> String {
+        let mut toml = format!("[{table}]\ndeadzone = {}\n[{table}.keys]\n", self.deadzone);
+        for key in LAYOUT {
+            let inputs: Vec<String> = self.bindings[key as usize].iter().map(|i| format!("{:?}", i.to_string())).collect();
+            toml += &format!("{key:X} = [{}]\n", inputs.join(", "));
+        }
+        toml
+    }
+
+    pub fn keys(&self, pressed: impl Iterator<Item = PadInput>) -> [bool; 16] {
+        let mut chip_keys = [false; 16];
+        for input in pressed {
+            for (key, inputs) in self.bindings.iter().enumerate() {
+                if inputs.contains(&input) {
+                    chip_keys[key] = true;
+                }
+            }
+        }
+        chip_keys
+    }
+}
+
+// `[gamepad]` table of a keymap file, also allowed per ROM.
+//
+//     [gamepad]
+//     deadzone = 12000
+//     keys = { 5 = ["a", "rightshoulder"], 4 = ["dpleft", "leftx-"] }
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamepadSection {
+    deadzone: Option<i16>,
+    keys: BTreeMap<String, Vec<String>>
+}
+
+// Layers the bindings like `input::build_keymap`: defaults, each file and its section
+// for `rom`, then `binds` and `deadzone` from the command line.
+pub fn build_padmap(files: &[&KeymapFile], rom: &str, binds: &[(u8, Vec<PadInput>)], deadzone: Option<i16>) -> Result<PadMap, String> {
+    let mut map = PadMap::default();
+    for section in files.iter().flat_map(|f| f.gamepad(rom)) {
+        if let Some(deadzone) = section.deadzone {
+            map.deadzone = deadzone;
+        }
+        for (key, inputs) in &section.keys {
+            let inputs = inputs.iter().map(|i| parse_pad_input(i)).collect::<Result<Vec<_>, _>>()?;
+            map.bind(parse_chip_key(key)?, &inputs);
+        }
+    }
+
+    for (key, inputs) in binds {
+        map.bind(*key, inputs);
+    }
+    if let Some(deadzone) = deadzone {
+        map.deadzone = deadzone;
+    }
+    Ok(map)
+}
+
+// Controller button for a direction or button named in the ROM database.
+pub fn rom_input(name: &str) -> Option<PadInput> {
+    Some(PadInput::Button(match name {
+        "up" => Button::DPadUp,
+        "down" => Button::DPadDown,
+        "left" => Button::DPadLeft,
+        "right" => Button::DPadRight,
+        "a" => Button::A,
+        "b" => Button::B,
+        _ => return None
+    }))
+}
+
+// Parses an SDL controller button name like `a` or `dpup`, or an axis name followed by
+// the direction like `leftx-` or `righttrigger+`.
+pub fn parse_pad_input(s: &str) -> Result<PadInput, String> {
+    let s = s.trim();
+    let axis = s.strip_suffix('+').map(|a| (a, true)).or_else(|| s.strip_suffix('-').map(|a| (a, false)));
+    let input = match axis {
+        Some((axis, positive)) => Axis::from_string(axis).map(|a| PadInput::Axis(a, positive)),
+        None => Button::from_string(s).map(PadInput::Button)
+    };
+    input.ok_or_else(|| format!("`{s}` is not a controller button, or an axis followed by + or -"))
+}
+
+// Parses `5=a,dpup`, a CHIP-8 key followed by the controller inputs bound to it. For use as a clap value parser.
+pub fn parse_pad_binding(s: &str) -> Result<(u8, Vec<PadInput>), String> {
+    let (key, inputs) = s.split_once('=').ok_or("expected KEY=INPUT[,INPUT...]")?;
+    let inputs = inputs.split(',').map(parse_pad_input).collect::<Result<Vec<_>, _>>()?;
+    Ok((parse_chip_key(key)?, inputs))
+}
+
+// Open controllers, kept up to date as they are plugged in and out.
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>
+}
+
+impl Gamepads {
+    // SDL sends an added event for every controller already connected, so they are opened by `handle`.
+    pub fn new(sdl: &Sdl) -> Result<Self, String> {
+        Ok(Gamepads { subsystem: sdl.game_controller()?, controllers: Vec::new() })
+    }
+
+    pub fn handle(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(controller) => {
+                    println!("Controller connected: {}", controller.name());
+                    self.controllers.push(controller);
+                }
+                Err(e) => println!("Couldn't open controller {which}: {e}")
+            },
+            // Added events carry the device index, removed events the instance id.
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.controllers.retain(|c| {
+                    let removed = c.instance_id() == which;
+                    if removed {
+                        println!("Controller disconnected: {}", c.name());
+                    }
+                    !removed
+                });
+            }
+            _ => {}
+        }
+    }
+}
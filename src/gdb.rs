@@ -0,0 +1,397 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::cpu::CPU;
+
+// Register numbering used by `g`/`G`/`p`/`P`, all little-endian:
+// 0-15 V0-VF (1 byte), 16 I (2 bytes), 17 PC (2 bytes), 18 SP, 19 DT, 20 ST (1 byte each).
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+const REG_COUNT: usize = 21;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Halted,
+    Running,
+    Stepping
+}
+
+// A GDB Remote Serial Protocol stub listening on a local TCP port.
+// Execution is halted until a client connects and continues, and resumes freely when it detaches.
+pub struct GdbStub {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    breakpoints: Vec<u16>,
+    state: State,
+    // Set when resuming so the breakpoint at the current PC doesn't immediately re-trigger.
+    resumed: bool,
+    pub quit: bool
+}
+
+impl GdbStub {
+    pub fn listen(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        println!("Waiting for GDB connection on 127.0.0.1:{port}");
+
+        Ok(GdbStub {
+            listener,
+            stream: None,
+            buffer: Vec::new(),
+            breakpoints: Vec::new(),
+            state: State::Halted,
+            resumed: false,
+            quit: false
+        })
+    }
+
+    // Services the connection and decides whether the CPU may execute its next instruction.
+    pub fn should_tick(&mut self, cpu: &mut CPU) -> bool {
+        self.poll(cpu);
+
+        if self.state == State::Halted {
+            return false;
+        }
+
+        let resumed = std::mem::take(&mut self.resumed);
+        if !resumed && self.breakpoints.contains(&cpu.pc()) {
+            self.stop(SIGTRAP);
+            return false;
+        }
+
+        true
+    }
+
+    pub fn after_tick(&mut self) {
+        if self.state == State::Stepping {
+            self.stop(SIGTRAP);
+        }
+    }
+
+    fn poll(&mut self, cpu: &mut CPU) {
+        if self.stream.is_none() {
+            match self.listener.accept() {
+                Ok((stream, addr)) => {
+                    if stream.set_nonblocking(true).is_ok() {
+                        println!("GDB client connected from {addr}");
+                        self.stream = Some(stream);
+                        self.state = State::Halted;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => println!("GDB accept failed: {e}")
+            }
+        }
+
+        let Some(stream) = self.stream.as_mut() else { return };
+
+        let mut chunk = [0u8; 1024];
+        loop {
+            match stream.read(&mut chunk) {
+                Ok(0) => {
+                    self.disconnect();
+                    return;
+                }
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.disconnect();
+                    return;
+                }
+            }
+        }
+
+        while let Some(packet) = self.next_packet() {
+            self.handle(&packet, cpu);
+        }
+    }
+
+    // Pops the next complete packet off the buffer, acknowledging it.
+    fn next_packet(&mut self) -> Option<String> {
+        loop {
+            match self.buffer.first()? {
+                b'+' | b'-' => {
+                    self.buffer.remove(0);
+                }
+                // Ctrl-C from the client, sent outside of any packet.
+                0x03 => {
+                    self.buffer.remove(0);
+                    if self.state != State::Halted {
+                        self.stop(SIGINT);
+                    }
+                }
+                b'$' => {
+                    let end = self.buffer.iter().position(|b| *b == b'#')?;
+                    if self.buffer.len() < end + 3 {
+                        return None;
+                    }
+                    let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let checksum = std::str::from_utf8(&packet[end + 1..])
+                        .ok()
+                        .and_then(|c| u8::from_str_radix(c, 16).ok());
+
+                    if checksum != Some(checksum_of(data)) {
+                        self.send_raw(b"-");
+                        continue;
+                    }
+                    self.send_raw(b"+");
+                    return Some(String::from_utf8_lossy(data).into_owned());
+                }
+                _ => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &str, cpu: &mut CPU) {
+        let (cmd, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+        let reply = match cmd {
+            "?" => format!("S{SIGTRAP:02x}"),
+            "g" => hex(&(0..REG_COUNT).flat_map(|r| read_reg(cpu, r)).collect::<Vec<u8>>()),
+            "G" => match unhex(args) {
+                Some(bytes) => {
+                    let mut regs = Vec::new();
+                    let mut rest = bytes.as_slice();
+                    for r in 0..REG_COUNT {
+                        let size = reg_size(r);
+                        if rest.len() < size {
+                            break;
+                        }
+                        regs.push((r, &rest[..size]));
+                        rest = &rest[size..];
+                    }
+                    // Nothing is written if any value is rejected.
+                    if regs.iter().all(|(r, value)| reg_accepts(*r, value)) {
+                        for (r, value) in regs {
+                            write_reg(cpu, r, value);
+                        }
+                        "OK".to_string()
+                    }
+                    else {
+                        "E01".to_string()
+                    }
+                }
+                None => "E01".to_string()
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(r) if r < REG_COUNT => hex(&read_reg(cpu, r)),
+                _ => "E01".to_string()
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(r, val)| {
+                    Some((usize::from_str_radix(r, 16).ok()?, unhex(val)?))
+                });
+                match parsed {
+                    Some((r, bytes)) if r < REG_COUNT && bytes.len() == reg_size(r) && reg_accepts(r, &bytes) => {
+                        write_reg(cpu, r, &bytes);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string()
+                }
+            }
+            "m" => read_memory(cpu, args),
+            "M" => {
+                let parsed = args.split_once(':').and_then(|(range, data)| Some((parse_range(range)?, unhex(data)?)));
+                match parsed {
+                    Some(((addr, len), bytes)) if bytes.len() == len && addr.checked_add(len).is_some_and(|end| end <= cpu.memory.len()) => {
+                        cpu.memory[addr..addr + len].copy_from_slice(&bytes);
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string()
+                }
+            }
+            "c" | "s" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    if !pc_in_memory(addr) {
+                        self.send("E01");
+                        return;
+                    }
+                    cpu.set_pc(addr);
+                }
+                self.state = if cmd == "c" { State::Running } else { State::Stepping };
+                self.resumed = true;
+                // The stop reply is sent once execution halts again.
+                return;
+            }
+            "Z" | "z" => match parse_breakpoint(args) {
+                // Only software breakpoints are supported.
+                Some((0, addr)) => {
+                    self.breakpoints.retain(|b| *b != addr);
+                    if cmd == "Z" {
+                        self.breakpoints.push(addr);
+                    }
+                    "OK".to_string()
+                }
+                Some(_) => String::new(),
+                None => "E01".to_string()
+            },
+            "H" => "OK".to_string(),
+            "q" => match args {
+                a if a.starts_with("Supported") => "PacketSize=1000;swbreak+".to_string(),
+                "Attached" => "1".to_string(),
+                "C" => "QC1".to_string(),
+                "fThreadInfo" => "m1".to_string(),
+                "sThreadInfo" => "l".to_string(),
+                _ => String::new()
+            },
+            "D" => {
+                self.send("OK");
+                self.disconnect();
+                return;
+            }
+            "k" => {
+                self.quit = true;
+                self.disconnect();
+                return;
+            }
+            _ => String::new()
+        };
+
+        self.send(&reply);
+    }
+
+    fn stop(&mut self, signal: u8) {
+        self.state = State::Halted;
+        self.send(&format!("S{signal:02x}"));
+    }
+
+    fn disconnect(&mut self) {
+        println!("GDB client disconnected");
+        self.stream = None;
+        self.buffer.clear();
+        self.breakpoints.clear();
+        self.state = State::Running;
+    }
+
+    fn send(&mut self, data: &str) {
+        let packet = format!("${data}#{:02x}", checksum_of(data.as_bytes()));
+        self.send_raw(packet.as_bytes());
+    }
+
+    fn send_raw(&mut self, mut data: &[u8]) {
+        let Some(stream) = self.stream.as_mut() else { return };
+        while !data.is_empty() {
+            match stream.write(data) {
+                Ok(n) => data = &data[n..],
+                Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
+                Err(_) => break
+            }
+        }
+        if !data.is_empty() {
+            self.disconnect();
+        }
+    }
+}
+
+fn reg_size(r: usize) -> usize {
+    match r {
+        REG_I | REG_PC => 2,
+        _ => 1
+    }
+}
+
+// PC must leave room for a whole instruction in memory.
+fn pc_in_memory(pc: u16) -> bool {
+    (pc as usize) + 1 < 0x1000
+}
+
+fn reg_accepts(r: usize, bytes: &[u8]) -> bool {
+    r != REG_PC || pc_in_memory(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_reg(cpu: &CPU, r: usize) -> Vec<u8> {
+    match r {
+        0..=15 => vec![cpu.v()[r]],
+        REG_I => cpu.i().to_le_bytes().to_vec(),
+        REG_PC => cpu.pc().to_le_bytes().to_vec(),
+        REG_SP => vec![cpu.sp()],
+        REG_DT => vec![cpu.dt()],
+        REG_ST => vec![cpu.st()],
+        _ => Vec::new()
+    }
+}
+
+fn write_reg(cpu: &mut CPU, r: usize, bytes: &[u8]) {
+    let word = || u16::from_le_bytes([bytes[0], bytes[1]]);
+    match r {
+        0..=15 => cpu.set_v(r as u8, bytes[0]),
+        REG_I => cpu.set_i(word()),
+        REG_PC => cpu.set_pc(word()),
+        REG_SP => cpu.set_sp(bytes[0]),
+        REG_DT => cpu.set_dt(bytes[0]),
+        REG_ST => cpu.set_st(bytes[0]),
+        _ => {}
+    }
+}
+
+// Reply to `m addr,len`. Ranges running off the end are cut short, ones starting past it are an error,
+// an empty reply would tell the client `m` isn't supported.
+fn read_memory(cpu: &CPU, args: &str) -> String {
+    match parse_range(args) {
+        Some((addr, len)) if addr < cpu.memory.len() => match addr.checked_add(len) {
+            Some(end) => hex(&cpu.memory[addr..end.min(cpu.memory.len())]),
+            None => "E01".to_string()
+        },
+        _ => "E01".to_string()
+    }
+}
+
+// Parses `addr,len`.
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((usize::from_str_radix(addr, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+}
+
+// Parses `type,addr,kind` into type and address.
+fn parse_breakpoint(args: &str) -> Option<(u8, u16)> {
+    let mut parts = args.split(',');
+    let kind = parts.next()?.parse().ok()?;
+    let addr = u16::from_str_radix(parts.next()?, 16).ok()?;
+    Some((kind, addr))
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QuirkConfig;
+
+    #[test]
+    fn read_memory_ranges() {
+        let mut cpu = CPU::new(QuirkConfig::default().quirk());
+        cpu.memory[0xFFE..].copy_from_slice(&[0xAB, 0xCD]);
+        assert_eq!(read_memory(&cpu, "ffe,2"), "abcd");
+        // Cut short at the end of memory.
+        assert_eq!(read_memory(&cpu, "ffe,4"), "abcd");
+        assert_eq!(read_memory(&cpu, "1000,4"), "E01");
+        assert_eq!(read_memory(&cpu, "ffe,ffffffffffffffff"), "E01");
+        assert_eq!(read_memory(&cpu, "ffe"), "E01");
+    }
+}
@@ -1,23 +1,351 @@
-use sdl2::{EventPump, keyboard::Keycode};
-
-pub fn get_keys(events: &EventPump) -> [bool; 16] {
-    let pressed_keys: Vec<Keycode> = events
-        .keyboard_state()
-        .pressed_scancodes()
-        .filter_map(Keycode::from_scancode)
-        .collect();
-
-    let mut chip_keys = [false; 16];
-    for key in pressed_keys {
-        let i = match key {
-            Keycode::Num1 => 0x1, Keycode::Num2 => 0x2, Keycode::Num3 => 0x3, Keycode::Num4 => 0xC,
-            Keycode::Q    => 0x4, Keycode::W    => 0x5, Keycode::E    => 0x6, Keycode::R    => 0xD,
-            Keycode::A    => 0x7, Keycode::S    => 0x8, Keycode::D    => 0x9, Keycode::F    => 0xE,
-            Keycode::Z    => 0xA, Keycode::X    => 0x0, Keycode::C    => 0xB, Keycode::V    => 0xF,
-            _ => continue
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use sdl2::{event::Event, keyboard::Keycode};
+use serde::Deserialize;
+
+use crate::gamepad::{GamepadSection, PadInput, PadMap};
+use crate::keypad::Keypad;
+
+// CHIP-8 keys in the order they're laid out on the keypad, row by row.
+pub const LAYOUT: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF
+];
+
+// Host keys for each position of LAYOUT, the 4x4 block under 1234 on each keyboard layout.
+pub const PRESETS: [(&str, [Keycode; 16]); 5] = {
+    use Keycode::*;
+    [
+        ("qwerty", [Num1, Num2, Num3, Num4, Q, W, E, R, A, S, D, F, Z, X, C, V]),
+        ("qwertz", [Num1, Num2, Num3, Num4, Q, W, E, R, A, S, D, F, Y, X, C, V]),
+        ("azerty", [Num1, Num2, Num3, Num4, A, Z, E, R, Q, S, D, F, W, X, C, V]),
+        ("dvorak", [Num1, Num2, Num3, Num4, Quote, Comma, Period, P, A, O, E, U, Semicolon, Q, J, K]),
+        ("colemak", [Num1, Num2, Num3, Num4, Q, W, F, P, A, R, S, T, Z, X, C, D])
+    ]
+};
+
+// Host keys bound to each CHIP-8 key, any of them presses it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymap {
+    bindings: [Vec<Keycode>; 16]
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::preset(PRESETS[0].1)
+    }
+}
+
+impl Keymap {
+    fn preset(keys: [Keycode; 16]) -> Self {
+        let mut keymap = Keymap { bindings: Default::default() };
+        for (key, host) in LAYOUT.iter().zip(keys) {
+            keymap.bindings[*key as usize].push(host);
+        }
+        keymap
+    }
+
+    // Replaces the bindings of `key`.
+    pub fn bind(&mut self, key: u8, hosts: &[Keycode]) {
+        self.bindings[key as usize] = hosts.to_vec();
+    }
+
+    // Binds `host` to `key` alone, taking it away from any other key.
+    pub fn rebind(&mut self, key: u8, host: Keycode) {
+        for hosts in self.bindings.iter_mut() {
+            hosts.retain(|h| *h != host);
+        }
+        self.bind(key, &[host]);
+    }
+
+    // Adds `host` to the keys that press `key`.
+    pub fn add(&mut self, key: u8, host: Keycode) {
+        let hosts = &mut self.bindings[key as usize & 0xF];
+        if !hosts.contains(&host) {
+            hosts.push(host);
+        }
+    }
+
+    pub fn keys(&self, pressed: impl Iterator<Item = Keycode>) -> [bool; 16] {
+        let mut chip_keys = [false; 16];
+        for host in pressed {
+            for (key, hosts) in self.bindings.iter().enumerate() {
+                if hosts.contains(&host) {
+                    chip_keys[key] = true;
+                }
+            }
+        }
+        chip_keys
+    }
+
+    // The bindings in keymap file syntax, so they can be saved.
+    pub fn to_toml(&self, table: &str) -> String {
+        let mut toml = format!("[{table}]\n");
+        for key in LAYOUT {
+            let hosts: Vec<String> = self.bindings[key as usize].iter().map(|h| format!("{:?}", h.name())).collect();
+            toml += &format!("{key:X} = [{}]\n", hosts.join(", "));
+        }
+        toml
+    }
+}
+
+// Keymap file, bindings under `roms` apply only when running a ROM with that name, see `config::rom_name`.
+// Controller bindings go in a `gamepad` table, see `gamepad::GamepadSection`.
+//
+//     preset = "azerty"
+//     [keys]
+//     5 = ["Z", "Up"]
+//     [roms.brix]
+//     keys = { 4 = ["Left"], 6 = ["Right"] }
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeymapFile {
+    preset: Option<String>,
+    keys: BTreeMap<String, Vec<String>>,
+    gamepad: GamepadSection,
+    roms: BTreeMap<String, RomKeymap>
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RomKeymap {
+    preset: Option<String>,
+    keys: BTreeMap<String, Vec<String>>,
+    gamepad: GamepadSection
+}
+
+impl KeymapFile {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Couldn't read {}: {e}", path.display()))?;
+        toml::from_str(&text).map_err(|e| format!("Invalid keymap {}: {e}", path.display()))
+    }
+
+    fn rom(&self, rom: &str) -> Option<&RomKeymap> {
+        self.roms.iter().find(|(name, _)| name.eq_ignore_ascii_case(rom)).map(|(_, section)| section)
+    }
+
+    // Controller bindings for `rom`, the global ones first.
+    pub fn gamepad(&self, rom: &str) -> Vec<&GamepadSection> {
+        let mut sections = vec![&self.gamepad];
+        sections.extend(self.rom(rom).map(|r| &r.gamepad));
+        sections
+    }
+}
+
+// Layers the bindings: `preset` (or the last file's preset, or qwerty), then for each file its keys
+// and its section for `rom`, then `binds` from the command line. A section's preset replaces the
+// bindings so far, unless `preset` was given on the command line.
+pub fn build_keymap(preset: Option<&Keymap>, files: &[&KeymapFile], rom: &str, binds: &[(u8, Vec<Keycode>)]) -> Result<Keymap, String> {
+    let mut keymap = match (preset, files.iter().rev().find_map(|f| f.preset.as_deref())) {
+        (Some(preset), _) => preset.clone(),
+        (None, Some(name)) => parse_preset(name)?,
+        (None, None) => Keymap::default()
+    };
+
+    for file in files {
+        apply_keys(&mut keymap, &file.keys)?;
+        if let Some(section) = file.rom(rom) {
+            if let Some(name) = section.preset.as_deref().filter(|_| preset.is_none()) {
+                keymap = parse_preset(name)?;
+            }
+            apply_keys(&mut keymap, &section.keys)?;
+        }
+    }
+
+    for (key, hosts) in binds {
+        keymap.bind(*key, hosts);
+    }
+    Ok(keymap)
+}
+
+fn apply_keys(keymap: &mut Keymap, keys: &BTreeMap<String, Vec<String>>) -> Result<(), String> {
+    for (key, hosts) in keys {
+        let key = parse_chip_key(key)?;
+        let hosts = hosts.iter().map(|h| parse_host_key(h)).collect::<Result<Vec<_>, _>>()?;
+        keymap.bind(key, &hosts);
+    }
+    Ok(())
+}
+
+pub fn parse_chip_key(s: &str) -> Result<u8, String> {
+    u8::from_str_radix(s.trim(), 16).ok().filter(|k| *k < 16 && s.trim().len() == 1)
+        .ok_or_else(|| format!("`{s}` is not a CHIP-8 key, expected 0-F"))
+}
+
+fn parse_host_key(s: &str) -> Result<Keycode, String> {
+    Keycode::from_name(s.trim()).ok_or_else(|| format!("`{s}` is not a key name"))
+}
+
+// Host key for a direction or button named in the ROM database.
+pub fn rom_key(name: &str) -> Option<Keycode> {
+    Some(match name {
+        "up" => Keycode::Up,
+        "down" => Keycode::Down,
+        "left" => Keycode::Left,
+        "right" => Keycode::Right,
+        "a" => Keycode::Space,
+        "b" => Keycode::LShift,
+        _ => return None
+    })
+}
+
+// Parses the name of a layout preset. For use as a clap value parser.
+pub fn parse_preset(s: &str) -> Result<Keymap, String> {
+    PRESETS.iter().find(|(n, _)| n.eq_ignore_ascii_case(s)).map(|(_, keys)| Keymap::preset(*keys))
+        .ok_or_else(|| {
+            let names: Vec<&str> = PRESETS.iter().map(|(n, _)| *n).collect();
+            format!("`{s}` is not one of the presets: {}", names.join(", "))
+        })
+}
+
+// Parses `5=W,Up`, a CHIP-8 key followed by the host keys bound to it. For use as a clap value parser.
+pub fn parse_binding(s: &str) -> Result<(u8, Vec<Keycode>), String> {
+    let (key, hosts) = s.split_once('=').ok_or("expected KEY=HOST[,HOST...]")?;
+    let hosts = hosts.split(',').map(parse_host_key).collect::<Result<Vec<_>, _>>()?;
+    Ok((parse_chip_key(key)?, hosts))
+}
+
+// Asks for a host key for each CHIP-8 key in turn, in keypad order.
+pub struct Rebinding {
+    step: usize
+}
+
+impl Rebinding {
+    pub fn start() -> Self {
+        Rebinding { step: 0 }
+    }
+
+    // The CHIP-8 key waiting for a binding, None once every key has one.
+    pub fn current(&self) -> Option<u8> {
+        LAYOUT.get(self.step).copied()
+    }
+
+    pub fn bind(&mut self, keymap: &mut Keymap, host: Keycode) {
+        if let Some(key) = self.current() {
+            keymap.rebind(key, host);
+            self.step += 1;
+        }
+    }
+}
+
+// Host inputs currently held, turned into presses and releases of the keypad as their events arrive.
+// A CHIP-8 key bound to several inputs stays down until all of them are up.
+#[derive(Default)]
+pub struct HeldInputs {
+    keys: Vec<Keycode>,
+    // Controller instance id and input.
+    pads: Vec<(u32, PadInput)>
+}
+
+impl HeldInputs {
+    pub fn handle(&mut self, event: &Event, keymap: &Keymap, padmap: &PadMap, keypad: &mut Keypad) {
+        let deadzone = padmap.deadzone as i32;
+        let changed = match *event {
+            Event::KeyDown { keycode: Some(host), .. } => hold(&mut self.keys, host, true),
+            Event::KeyUp { keycode: Some(host), .. } => hold(&mut self.keys, host, false),
+            Event::ControllerButtonDown { which, button, .. } => hold(&mut self.pads, (which, PadInput::Button(button)), true),
+            Event::ControllerButtonUp { which, button, .. } => hold(&mut self.pads, (which, PadInput::Button(button)), false),
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                let positive = hold(&mut self.pads, (which, PadInput::Axis(axis, true)), value as i32 > deadzone);
+                let negative = hold(&mut self.pads, (which, PadInput::Axis(axis, false)), (value as i32) < -deadzone);
+                positive || negative
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                let held = self.pads.len();
+                self.pads.retain(|(id, _)| *id != which);
+                self.pads.len() != held
+            }
+            _ => false
         };
-        chip_keys[i] = true;
+        if changed {
+            self.sync(keymap, padmap, keypad);
+        }
+    }
+
+    // Lets go of everything, for when the game shouldn't see the input.
+    pub fn release_all(&mut self, keypad: &mut Keypad) {
+        self.keys.clear();
+        self.pads.clear();
+        for key in 0..16 {
+            if keypad.is_pressed(key) {
+                keypad.release(key);
+            }
+        }
+    }
+
+    fn sync(&self, keymap: &Keymap, padmap: &PadMap, keypad: &mut Keypad) {
+        let keys = keymap.keys(self.keys.iter().copied());
+        let pads = padmap.keys(self.pads.iter().map(|(_, input)| *input));
+        for (key, down) in keys.iter().zip(pads).enumerate().map(|(key, (k, p))| (key as u8, *k || p)) {
+            if down && !keypad.is_pressed(key) {
+                keypad.press(key);
+            }
+            else if !down && keypad.is_pressed(key) {
+                keypad.release(key);
+            }
+        }
     }
+}
 
-    chip_keys
-}
\ No newline at end of file
+// Adds or removes `input` from `held`, returns whether that changed anything.
+fn hold<T: PartialEq>(held: &mut Vec<T>, input: T, down: bool) -> bool {
+    let index = held.iter().position(|h| *h == input);
+    match (index, down) {
+        (None, true) => held.push(input),
+        (Some(i), false) => {
+            held.swap_remove(i);
+        }
+        _ => return false
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::controller::Button;
+    use sdl2::keyboard::Mod;
+
+    fn key(host: Keycode, down: bool) -> Event {
+        let (timestamp, window_id, scancode, keymod, repeat) = (0, 0, None, Mod::NOMOD, false);
+        if down {
+            Event::KeyDown { timestamp, window_id, keycode: Some(host), scancode, keymod, repeat }
+        }
+        else {
+            Event::KeyUp { timestamp, window_id, keycode: Some(host), scancode, keymod, repeat }
+        }
+    }
+
+    #[test]
+    fn press_and_release_in_one_frame() {
+        let (keymap, padmap) = (Keymap::default(), PadMap::default());
+        let mut keypad = Keypad::new(true);
+        let mut held = HeldInputs::default();
+        keypad.wait_key();
+        // W is 5 on qwerty, both events arrive before the next frame runs.
+        held.handle(&key(Keycode::W, true), &keymap, &padmap, &mut keypad);
+        held.handle(&key(Keycode::W, false), &keymap, &padmap, &mut keypad);
+        assert_eq!(keypad.wait_key(), Some(0x5));
+    }
+
+    #[test]
+    fn key_held_by_another_input_stays_down() {
+        let (mut keymap, padmap) = (Keymap::default(), PadMap::default());
+        keymap.add(0x5, Keycode::Up);
+        let mut keypad = Keypad::new(false);
+        let mut held = HeldInputs::default();
+        held.handle(&key(Keycode::W, true), &keymap, &padmap, &mut keypad);
+        held.handle(&Event::ControllerButtonDown { timestamp: 0, which: 0, button: Button::A }, &keymap, &padmap, &mut keypad);
+        held.handle(&key(Keycode::Up, true), &keymap, &padmap, &mut keypad);
+        held.handle(&key(Keycode::W, false), &keymap, &padmap, &mut keypad);
+        assert!(keypad.is_pressed(0x5));
+        held.handle(&key(Keycode::Up, false), &keymap, &padmap, &mut keypad);
+        assert!(keypad.is_pressed(0x5));
+        held.handle(&Event::ControllerButtonUp { timestamp: 0, which: 0, button: Button::A }, &keymap, &padmap, &mut keypad);
+        assert!(!keypad.is_pressed(0x5));
+    }
+}
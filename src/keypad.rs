@@ -0,0 +1,164 @@
+// State of the 16 key hex keypad, fed press and release events by the input layer.
+// Also tracks FX0A, which has to see a key go down (and with the release quirk, back up).
+pub struct Keypad {
+    pressed: [bool; 16],
+    wait: Wait,
+    // FX0A finishes when the key is released rather than when it is pressed, like the VIP.
+    wait_release: bool
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Wait {
+    Idle,
+    Waiting,
+    Held(u8),
+    Done(u8)
+}
+
+impl Keypad {
+    pub fn new(wait_release: bool) -> Self {
+        Keypad { pressed: [false; 16], wait: Wait::Idle, wait_release }
+    }
+
+    pub fn press(&mut self, key: u8) {
+        let key = key & 0xF;
+        self.pressed[key as usize] = true;
+        if self.wait == Wait::Waiting {
+            self.wait = if self.wait_release { Wait::Held(key) } else { Wait::Done(key) };
+        }
+    }
+
+    pub fn release(&mut self, key: u8) {
+        let key = key & 0xF;
+        self.pressed[key as usize] = false;
+        if self.wait == Wait::Held(key) {
+            self.wait = Wait::Done(key);
+        }
+    }
+
+    // Turns the current state of every key into press and release events, for headless runs and
+    // tests that script which keys are down each frame. The window feeds events as they arrive.
+    pub fn update(&mut self, keys: [bool; 16]) {
+        for (key, down) in keys.into_iter().enumerate() {
+            if down && !self.pressed[key] {
+                self.press(key as u8);
+            }
+            else if !down && self.pressed[key] {
+                self.release(key as u8);
+            }
+        }
+    }
+
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.pressed[(key & 0xF) as usize]
+    }
+
+    // Called each time FX0A executes, returns the key once the wait is over.
+    // Keys held from before the wait only count when waiting for a release, so a
+    // held key isn't read again by the next FX0A.
+    pub fn wait_key(&mut self) -> Option<u8> {
+        if self.wait == Wait::Idle {
+            self.wait = Wait::Waiting;
+            if self.wait_release {
+                if let Some(key) = self.pressed.iter().position(|k| *k) {
+                    self.wait = Wait::Held(key as u8);
+                }
+            }
+        }
+
+        match self.wait {
+            Wait::Done(key) => {
+                self.wait = Wait::Idle;
+                Some(key)
+            }
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_tracks_state() {
+        let mut keypad = Keypad::new(true);
+        let mut keys = [false; 16];
+        keys[0x5] = true;
+        keypad.update(keys);
+        assert!(keypad.is_pressed(0x5));
+        assert!(!keypad.is_pressed(0x6));
+
+        keypad.update([false; 16]);
+        assert!(!keypad.is_pressed(0x5));
+    }
+
+    #[test]
+    fn wait_release_needs_press_and_release() {
+        let mut keypad = Keypad::new(true);
+        assert_eq!(keypad.wait_key(), None);
+        keypad.press(0xA);
+        assert_eq!(keypad.wait_key(), None);
+        keypad.release(0xA);
+        assert_eq!(keypad.wait_key(), Some(0xA));
+        // The wait is over, the next FX0A starts a new one.
+        assert_eq!(keypad.wait_key(), None);
+    }
+
+    #[test]
+    fn wait_release_accepts_key_already_held() {
+        let mut keypad = Keypad::new(true);
+        keypad.press(0x3);
+        assert_eq!(keypad.wait_key(), None);
+        keypad.release(0x3);
+        assert_eq!(keypad.wait_key(), Some(0x3));
+    }
+
+    #[test]
+    fn wait_release_ignores_other_keys_released() {
+        let mut keypad = Keypad::new(true);
+        keypad.wait_key();
+        keypad.press(0x1);
+        keypad.press(0x2);
+        keypad.release(0x2);
+        assert_eq!(keypad.wait_key(), None);
+        keypad.release(0x1);
+        assert_eq!(keypad.wait_key(), Some(0x1));
+    }
+
+    #[test]
+    fn wait_press_returns_on_press() {
+        let mut keypad = Keypad::new(false);
+        assert_eq!(keypad.wait_key(), None);
+        keypad.press(0xF);
+        assert_eq!(keypad.wait_key(), Some(0xF));
+    }
+
+    #[test]
+    fn wait_press_ignores_held_key() {
+        let mut keypad = Keypad::new(false);
+        keypad.press(0x4);
+        // Held from before the wait, only a new press counts.
+        assert_eq!(keypad.wait_key(), None);
+        keypad.release(0x4);
+        assert_eq!(keypad.wait_key(), None);
+        keypad.press(0x4);
+        assert_eq!(keypad.wait_key(), Some(0x4));
+    }
+
+    #[test]
+    fn held_key_not_read_twice() {
+        let mut keypad = Keypad::new(false);
+        let mut keys = [false; 16];
+        keys[0x7] = true;
+        keypad.wait_key();
+        keypad.update(keys);
+        assert_eq!(keypad.wait_key(), Some(0x7));
+
+        // Key still down on the following frames.
+        keypad.update(keys);
+        assert_eq!(keypad.wait_key(), None);
+        keypad.update(keys);
+        assert_eq!(keypad.wait_key(), None);
+    }
+}
@@ -1,59 +1,296 @@
-use std::{fs, time::Duration, thread, io};
+use std::{fs, path::{Path, PathBuf}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}, thread};
 
+use audio::{AudioRecorder, AudioSettings, Beeper, Waveform};
+
+use debugger::Debugger;
+use gdb::GdbStub;
+use emulator::Emulator;
+use recording::GifRecorder;
+use palette::Palette;
+use persistence::{Persistence, PersistenceMode};
+use trace::{TraceFormat, Tracer};
 use display::Display;
-use quirk::Quirk;
-use sdl2::{self, event::Event, keyboard::Keycode};
-use clap::Parser;
+use filter::Filter;
+use input::{HeldInputs, Keymap, KeymapFile, Rebinding};
+use gamepad::{Gamepads, PadInput, PadMap};
+use romdb::{RomDatabase, RomInfo};
+use config::{Config, Frontend};
+use quirk::{Quirk, QuirkArgs};
+use sdl2::{self, event::{Event, WindowEvent}, keyboard::{Keycode, Scancode}};
+use clap::{builder::TypedValueParser, value_parser, Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
 
 pub mod display;
 pub mod cpu;
 pub mod instruction;
 pub mod input;
+pub mod keypad;
 pub mod quirk;
+pub mod disasm;
+pub mod debugger;
+pub mod watch;
+pub mod overlay;
+pub mod gdb;
+pub mod trace;
+pub mod audio;
+pub mod wav;
+pub mod emulator;
+pub mod png;
+pub mod recording;
+pub mod palette;
+pub mod persistence;
+pub mod filter;
+pub mod gamepad;
+pub mod romdb;
+pub mod config;
+pub mod asm;
+pub mod commands;
+pub mod analysis;
+#[cfg(test)]
+mod conformance;
 
 #[derive(Parser)]
+#[command(version, about = "CHIP-8 emulator and tools")]
 pub struct CLI {
+    #[command(subcommand)]
+    pub command: Command
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run a ROM in a window, or headless.
+    Run(Box<RunArgs>),
+
+    /// Print the instructions of a ROM as source for `assemble`, with the address and opcode of each.
+    Disasm {
+        /// Path to the rom to disassemble.
+        rom_path: PathBuf,
+
+        /// Address to start from, the ROM is loaded at 0x200.
+        #[arg(long, default_value = "0x200", value_parser = parse_addr)]
+        start: u16,
+
+        /// Amount of instructions to print, (default up to the end of the ROM).
+        #[arg(long)]
+        count: Option<usize>
+    },
+
+    /// Assemble a source file in the syntax printed by `disasm` into a ROM.
+    Assemble {
+        /// Path to the source file.
+        source: PathBuf,
+
+        /// Path of the ROM to write, (default the source path with a .ch8 extension).
+        #[arg(short, long)]
+        output: Option<PathBuf>
+    },
+
+    /// Run a ROM headless for a fixed amount of frames and print the screen and its hash,
+    /// for checking test ROMs. Exits with an error if the hash differs from --expect.
+    Test {
+        /// Path to the rom to run.
+        rom_path: PathBuf,
+
+        /// Amount of 60Hz frames to run.
+        #[arg(long, default_value_t = 600, value_parser = value_parser!(u64).range(1..))]
+        frames: u64,
+
+        /// Amount of instructions executed per 60Hz frame.
+        #[arg(long, default_value_t = 30, value_parser = value_parser!(u8).range(1..))]
+        ticks_per_frame: u8,
+
+        /// SHA-1 the framebuffer is expected to have after the last frame.
+        #[arg(long)]
+        expect: Option<String>,
+
+        #[command(flatten)]
+        quirks: QuirkArgs
+    },
+
+    /// Print what is known about a ROM: size, hash, database entry, the platform it appears to be for,
+    /// its first instructions and the instructions whose behaviour depends on a quirk.
+    Info {
+        /// Path to the rom to inspect.
+        rom_path: PathBuf,
+
+        /// Directory with ROM database files, as for `run`.
+        #[arg(long)]
+        rom_db: Option<PathBuf>
+    },
+
+    /// Run a ROM unthrottled without any output and report the instruction rate.
+    Bench {
+        /// Path to the rom to run.
+        rom_path: PathBuf,
+
+        /// Amount of 60Hz frames to run.
+        #[arg(long, default_value_t = 6000, value_parser = value_parser!(u64).range(1..))]
+        frames: u64,
+
+        /// Amount of instructions executed per 60Hz frame.
+        #[arg(long, default_value_t = 30, value_parser = value_parser!(u8).range(1..))]
+        ticks_per_frame: u8,
+
+        #[command(flatten)]
+        quirks: QuirkArgs
+    },
+
+    /// Print a shell completion script to stdout.
+    Completions {
+        shell: Shell
+    }
+}
+
+// Boolean options take an optional value, so `--mute` switches an option on
+// and `--mute=false` switches off one enabled in a config file.
+#[derive(Args)]
+pub struct RunArgs {
     /// Path to the rom to load.
-    pub rom_path: std::path::PathBuf,
+    pub rom_path: PathBuf,
 
-    /// Scale to multiply the screen size, default 10.
-    #[arg(short, long)]
+    /// Initial window scale, also used for screenshots and recordings, (default 10).
+    #[arg(short, long, value_parser = value_parser!(u64).range(1..=64).map(|s| s as usize))]
     pub scale: Option<usize>,
 
-    /// .
-    #[arg(short, long)]
+    /// Extra delay in milliseconds after each instruction, for watching a program step by step, (default 0).
+    #[arg(long)]
     pub tick_delay: Option<u64>,
 
-    /// Amount of ticks between each render, (default 60).
-    #[arg(short='p', long)]
+    /// Amount of instructions executed per 60Hz frame, (default 30).
+    #[arg(long, value_parser = value_parser!(u8).range(1..))]
     pub ticks_per_frame: Option<u8>,
 
-    /// Debug mode, requires a key press to proceed execution, prints contents of registers and the current instruction at each cycle.
-    #[arg(short, long)]
-    pub debug: Option<bool>,
+    /// Frequency of the beep in Hz, (default 440).
+    #[arg(long, value_parser = parse_frequency)]
+    pub beep_freq: Option<f32>,
 
-    // Quirks
-    /// Opcodes [0x8XY1-3] will reset VF.
-    #[arg(short, long)]
-    pub vf_reset: Option<bool>,
+    /// Volume of the beep from 0.0 to 1.0, (default 0.25).
+    #[arg(long, value_parser = parse_volume)]
+    pub volume: Option<f32>,
 
-    /// Opcodes [0xFX55] and [0xFX65] will increment I.
-    #[arg(short, long)]
-    pub mem_inc: Option<bool>,
+    /// Waveform of the beep, (default square).
+    #[arg(long, value_enum)]
+    pub waveform: Option<Waveform>,
 
-    // Idk not implemented
-    // pub display_wait: Option<bool>,
-    
-    /// Clip sprites instead of wrapping when they go off screen.
-    #[arg(short, long)]
-    pub clipping: Option<bool>,
+    /// Disable sound.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub mute: Option<bool>,
+
+    /// Record the beeper to a WAV file, generated from emulated time.
+    #[arg(long)]
+    pub record_audio: Option<PathBuf>,
+
+    /// Run without a window or audio device for the amount of frames given by --frames.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub headless: Option<bool>,
+
+    /// Amount of 60Hz frames to run in headless mode, (default 600).
+    #[arg(long, value_parser = value_parser!(u64).range(1..))]
+    pub frames: Option<u64>,
+
+    /// Colour theme (classic, octo, lcd, amber, green, hotdog, cga) or comma separated hex colours
+    /// for background, foreground and the two extra plane colours, F9 cycles themes at runtime.
+    #[arg(long, value_parser = palette::parse_palette)]
+    pub palette: Option<Palette>,
+
+    /// Config file to use instead of config.toml in the user config dir.
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
+    /// Print the effective configuration, after merging config files and command line options, and exit.
+    #[arg(long)]
+    pub print_config: bool,
+
+    /// Directory with ROM database files in the community CHIP-8 database format, extending
+    /// the bundled one, (default chip-8/database under the user config dir).
+    #[arg(long)]
+    pub rom_db: Option<PathBuf>,
+
+    /// Don't guess the platform and quirks of ROMs that aren't in the database from their code.
+    #[arg(long)]
+    pub no_detect: bool,
+
+    /// Keyboard layout preset: qwerty, qwertz, azerty, dvorak or colemak, (default qwerty).
+    #[arg(long, value_parser = input::parse_preset)]
+    pub keymap: Option<Keymap>,
 
-    /// Shift use Vx for shifting instead of Vy in [0x8XY6] and [0x8XYE].
-    #[arg(short='x', long)]
-    pub shift_x: Option<bool>,
-    /// Opcode [0xBNNN] will jump to NNN + VX, where X is the highest nibble of NNN, instead of NNN + V0.
+    /// TOML file with key bindings: an optional `preset`, a `[keys]` table of CHIP-8 keys to host key
+    /// names like `5 = ["W", "Up"]`, a `[gamepad]` table and `[roms.brix]` sections for single ROMs,
+    /// named by file without the extension.
+    #[arg(long)]
+    pub keymap_file: Option<PathBuf>,
+
+    /// Bind host keys to a CHIP-8 key, e.g. `5=W,Up`, can be repeated. F8 rebinds every key at runtime.
+    #[arg(long, value_parser = input::parse_binding)]
+    pub bind: Vec<(u8, Vec<Keycode>)>,
+
+    /// Bind controller inputs to a CHIP-8 key, e.g. `5=a,leftx+`, can be repeated.
+    #[arg(long, value_parser = gamepad::parse_pad_binding)]
+    pub pad_bind: Vec<(u8, Vec<PadInput>)>,
+
+    /// How far a controller stick has to move before it presses a key, out of 32767, (default 8000).
+    #[arg(long, value_parser = value_parser!(i16).range(0..))]
+    pub deadzone: Option<i16>,
+
+    /// Start in fullscreen, F11 toggles fullscreen at runtime.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub fullscreen: Option<bool>,
+
+    /// Only scale the screen by whole numbers when the window is resized.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub integer_scaling: Option<bool>,
+
+    /// Reduce sprite flicker by blending recent frames, (default off).
+    #[arg(long, value_enum)]
+    pub persistence: Option<PersistenceMode>,
+
+    /// Strength of --persistence, the brightness kept per frame for phosphor (default 0.6)
+    /// or the amount of frames combined for deflicker (default 2).
+    #[arg(long, value_parser = parse_strength)]
+    pub persistence_strength: Option<f32>,
+
+    /// Software post-processing filters, comma separated, e.g. `scanlines,bloom` (default none).
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub filter: Vec<Filter>,
+
+    /// Record the screen to an animated GIF, F10 starts and stops a recording at any time.
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+
+    /// Save a PNG of the screen to this path on exit, F12 saves one at any time.
+    #[arg(long)]
+    pub screenshot: Option<PathBuf>,
+
+    /// Debug mode, starts paused with an interactive debugger prompt on stdin (breakpoints, stepping, register and memory inspection).
     #[arg(short, long)]
-    pub jump_vx: Option<bool>
+    pub debug: bool,
+
+    /// Show debugger panels (disassembly, registers, memory around I and keypad) next to the game.
+    #[arg(long)]
+    pub overlay: bool,
+
+    /// Listen for a GDB remote protocol client on this local TCP port, execution waits until one attaches.
+    #[arg(long, value_parser = value_parser!(u16).range(1..))]
+    pub gdb: Option<u16>,
+
+    /// Write a trace of every executed instruction to this file.
+    #[arg(long)]
+    pub trace: Option<PathBuf>,
+
+    /// Format of the trace file, (default text).
+    #[arg(long, value_enum)]
+    pub trace_format: Option<TraceFormat>,
+
+    /// Only trace instructions with a PC in this inclusive range, e.g. 0x200-0x2ff.
+    #[arg(long, value_parser = trace::parse_range)]
+    pub trace_range: Option<(u16, u16)>,
+
+    /// Amount of recently executed instructions printed when the CPU faults, (default 64).
+    #[arg(long)]
+    pub trace_history: Option<usize>,
+
+    #[command(flatten)]
+    pub quirks: QuirkArgs
 }
 
 pub struct CHIP8Options {
@@ -61,91 +298,417 @@ pub struct CHIP8Options {
     pub scale: usize,
     pub ticks_per_frame: u8,
     pub tick_delay: u64,
+    pub audio: AudioSettings,
+    pub mute: bool,
+    pub record_audio: Option<String>,
+    pub headless: bool,
+    pub frames: u64,
+    pub palette: Palette,
+    pub keymap: Keymap,
+    pub padmap: PadMap,
+    pub fullscreen: bool,
+    pub integer_scaling: bool,
+    pub persistence: PersistenceMode,
+    pub persistence_strength: f32,
+    pub filters: Vec<Filter>,
+    pub record: Option<String>,
+    pub screenshot: Option<String>,
     pub debug: bool,
+    pub overlay: bool,
+    pub gdb_port: Option<u16>,
+    pub trace_path: Option<String>,
+    pub trace_format: TraceFormat,
+    pub trace_range: Option<(u16, u16)>,
+    pub trace_history: usize,
     pub quirks: Quirk
 }
 
 fn main() {
-    let opts = parse_args();
+    match CLI::parse().command {
+        Command::Run(args) => run(*args),
+        Command::Disasm { rom_path, start, count } => commands::disasm(&rom_path, start, count),
+        Command::Assemble { source, output } => commands::assemble(&source, output.as_deref()),
+        Command::Test { rom_path, frames, ticks_per_frame, expect, quirks } => {
+            commands::test(&rom_path, frames, ticks_per_frame, expect.as_deref(), quirks);
+        }
+        Command::Info { rom_path, rom_db } => commands::info(&rom_path, rom_db.as_deref()),
+        Command::Bench { rom_path, frames, ticks_per_frame, quirks } => commands::bench(&rom_path, frames, ticks_per_frame, quirks),
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut CLI::command(), "chip-8", &mut std::io::stdout());
+        }
+    }
+}
 
-    let rom = fs::read(&opts.rom_path).expect(format!("Failed to read file at: {}", &opts.rom_path).as_str());
+fn run(cli: RunArgs) {
+    let rom = fs::read(&cli.rom_path).unwrap_or_else(|_| panic!("Failed to read file at: {}", cli.rom_path.display()));
 
-    let context = sdl2::init().unwrap();
+    let db_dir = cli.rom_db.clone().or_else(romdb::user_dir);
+    let db = RomDatabase::load(db_dir.as_deref()).unwrap_or_else(|e| panic!("Failed to load ROM database: {e}"));
+    let hash = romdb::sha1(&rom);
+    let info = match db.lookup(&hash) {
+        Some(info) => {
+            print_rom_info(&info);
+            Some(info)
+        }
+        None => {
+            println!("ROM {hash} is not in the database");
+            (!cli.no_detect).then(|| detect(&rom, &db, &cli.rom_path))
+        }
+    };
+
+    let opts = parse_args(cli, info.as_ref());
+
+    let mut chip = cpu::CPU::new(opts.quirks);
+    chip.load_rom(rom).unwrap_or_else(|e| {
+        eprintln!("Failed to load {}: {e}", opts.rom_path);
+        std::process::exit(1);
+    });
+
+    let mut emu = Emulator::new(chip, opts.ticks_per_frame, opts.tick_delay);
+
+    if opts.debug {
+        emu.debugger = Some(Debugger::attach());
+    }
+    emu.gdb = opts.gdb_port.map(|port| {
+        GdbStub::listen(port).unwrap_or_else(|e| panic!("Failed to listen on port {port}: {e}"))
+    });
+    if opts.trace_path.is_some() || opts.trace_history > 0 {
+        let path = opts.trace_path.as_ref().map(Path::new);
+        let tracer = Tracer::new(path, opts.trace_format, opts.trace_range, opts.trace_history)
+            .unwrap_or_else(|e| panic!("Failed to create trace file: {e}"));
+        emu.tracer = Some(tracer);
+    }
+    if let Some(path) = &opts.record_audio {
+        let recorder = AudioRecorder::create(Path::new(path), opts.audio)
+            .unwrap_or_else(|e| panic!("Failed to create audio recording: {e}"));
+        emu.recorder = Some(recorder);
+    }
+
+    if let Some(path) = &opts.record {
+        let video = GifRecorder::create(Path::new(path), opts.scale, opts.palette)
+            .unwrap_or_else(|e| panic!("Failed to create recording: {e}"));
+        emu.video = Some(video);
+    }
+
+    // The window can switch themes, the exit screenshot uses the one on screen at the end.
+    let palette = if opts.headless {
+        run_headless(&mut emu, opts.frames);
+        opts.palette
+    }
+    else {
+        run_window(&mut emu, &opts)
+    };
+
+    if let Some(path) = &opts.screenshot {
+        save_screenshot(Path::new(path), &emu, opts.scale, &palette);
+    }
+    emu.finish();
+}
+
+fn print_rom_info(info: &RomInfo) {
+    if info.authors.is_empty() {
+        println!("Loaded {}", info.title);
+    }
+    else {
+        println!("Loaded {} by {}", info.title, info.authors.join(", "));
+    }
+    if let Some(platform) = &info.platform {
+        // Only the quirks of later platforms are emulated, not their extra instructions.
+        let supported = ["originalChip8", "hybridVIP", "modernChip8"].contains(&platform.id.as_str());
+        println!("Platform: {}{}", platform.name, if supported { "" } else { " (not supported, running as CHIP-8)" });
+    }
+}
+
+// Guesses the platform and quirks of a ROM from its code, see `analysis::analyse`.
+fn detect(rom: &[u8], db: &RomDatabase, path: &Path) -> RomInfo {
+    let analysis = analysis::analyse(rom);
+    let info = analysis.rom_info(db, &config::rom_name(path));
+    let quirks: Vec<String> = config::quirk_settings(info.quirks).iter().map(|(name, value)| format!("{name}={value}")).collect();
+    if quirks.is_empty() {
+        println!("Detected platform: {}", analysis.platform);
+    }
+    else {
+        println!("Detected platform: {}, using {}", analysis.platform, quirks.join(", "));
+    }
+    info
+}
+
+fn timestamp() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+fn toggle_recording(emu: &mut Emulator, scale: usize, palette: Palette) {
+    match emu.video.take() {
+        Some(mut video) => match video.finish() {
+            Ok(()) => println!("Recording stopped"),
+            Err(e) => println!("Failed to finish recording: {e}")
+        },
+        None => {
+            let path = format!("recording-{}.gif", timestamp());
+            match GifRecorder::create(Path::new(&path), scale, palette) {
+                Ok(video) => {
+                    println!("Recording to {path}");
+                    emu.video = Some(video);
+                }
+                Err(e) => println!("Failed to start recording: {e}")
+            }
+        }
+    }
+}
+
+fn save_screenshot(path: &Path, emu: &Emulator, scale: usize, palette: &Palette) {
+    match display::screenshot(path, &emu.chip.vbuffer, scale, palette) {
+        Ok(()) => println!("Saved screenshot to {}", path.display()),
+        Err(e) => println!("Failed to save screenshot to {}: {e}", path.display())
+    }
+}
 
-    let mut display = Display::new(&context, opts.scale);
-    let mut chip = cpu::CPU::new(opts.quirks, opts.debug);
-    chip.load_rom(rom);
+// Runs for a fixed amount of frames as fast as possible, without a window, audio device or input.
+fn run_headless(emu: &mut Emulator, frames: u64) {
+    for _ in 0..frames {
+        if !emu.run_frame() {
+            break;
+        }
+    }
+}
 
-    // display.draw(&chip.vbuffer);
+// Returns the palette in use when the window closed.
+fn run_window(emu: &mut Emulator, opts: &CHIP8Options) -> Palette {
+    let mut keymap = opts.keymap.clone();
+    let mut rebinding: Option<Rebinding> = None;
+    let mut held = HeldInputs::default();
+
+    let context = sdl2::init().unwrap();
 
+    let mut display = Display::new(&context, opts.scale, opts.palette, opts.overlay, opts.integer_scaling);
+    if opts.fullscreen {
+        display.toggle_fullscreen();
+    }
+    display.persistence = Persistence::new(opts.persistence, opts.persistence_strength);
+    display.set_filters(&opts.filters, opts.scale);
     let mut event_pump = context.event_pump().unwrap();
+    let mut gamepads = Gamepads::new(&context).map_err(|e| println!("Controllers unavailable: {e}")).ok();
+
+    if !opts.mute {
+        emu.beeper = Beeper::new(&context, opts.audio).map_err(|e| println!("Audio unavailable: {e}")).ok();
+    }
+
+    let frame_time = Duration::from_secs(1) / audio::TIMER_HZ;
+    let mut next_frame = Instant::now() + frame_time;
 
     'running: loop {
-        
-        let keys = input::get_keys(&event_pump);
-        
         for event in event_pump.poll_iter() {
+            if let Some(gamepads) = gamepads.as_mut() {
+                gamepads.handle(&event);
+            }
+            if let Some(rebind) = rebinding.as_mut() {
+                match event {
+                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                        println!("Rebinding cancelled");
+                        rebinding = None;
+                        display.set_title("CHIP-8");
+                    }
+                    Event::KeyDown { keycode: Some(host), repeat: false, .. } => {
+                        rebind.bind(&mut keymap, host);
+                        if let Some(key) = rebind.current() {
+                            prompt_binding(&mut display, key);
+                        }
+                        else {
+                            println!("Key bindings, can be saved to a --keymap-file:\n{}", keymap.to_toml("keys"));
+                            rebinding = None;
+                            display.set_title("CHIP-8");
+                        }
+                    }
+                    Event::Quit { .. } => break 'running,
+                    _ => {}
+                }
+                continue;
+            }
+            held.handle(&event, &keymap, &opts.padmap, &mut emu.chip.keypad);
+
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
-                Event::KeyDown { keycode, .. } => {
-                    if keycode.unwrap() == Keycode::I {
-                        println!("PEEK RAM: ");
-                        let mut idx_str = String::new();
-                        io::stdin().read_line(&mut idx_str).expect("Failed to read index.");
-                        let idx = idx_str.trim().parse::<u16>().expect("Failed to parse idx_str");
-                        println!("RAM[0x{idx:0x}] = {}", chip.memory[idx as usize]);
-                    }
-                    if opts.debug {
-                        chip.tick(keys)
+                Event::KeyDown { keycode: Some(Keycode::F12), .. } => {
+                    let path = format!("screenshot-{}.png", timestamp());
+                    save_screenshot(Path::new(&path), emu, opts.scale, &display.palette);
+                }
+                Event::KeyDown { keycode: Some(Keycode::F10), .. } => toggle_recording(emu, opts.scale, display.palette),
+                Event::KeyDown { keycode: Some(Keycode::F11), .. } => display.toggle_fullscreen(),
+                Event::KeyDown { keycode: Some(Keycode::F8), .. } => {
+                    // The game doesn't see the keys being pressed to rebind them.
+                    held.release_all(&mut emu.chip.keypad);
+                    rebinding = Some(Rebinding::start());
+                    prompt_binding(&mut display, input::LAYOUT[0]);
+                }
+                Event::Window { win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed, .. } => {
+                    emu.chip.redraw = true;
+                }
+                Event::KeyDown { keycode: Some(Keycode::F9), .. } => {
+                    let (name, palette) = palette::next_theme(&display.palette);
+                    println!("Theme: {name}");
+                    display.palette = palette;
+                    if let Some(video) = emu.video.as_mut() {
+                        video.set_palette(palette);
                     }
+                    emu.chip.redraw = true;
                 }
                 _ => {}
             }
         }
-        if !opts.debug {
-            chip.tick(keys)
+
+        if !emu.run_frame() {
+            break 'running;
         }
 
-        if chip.redraw {
-            display.draw(&chip.vbuffer);
-            chip.redraw = false;
+        // The overlay changes with every instruction, so it is refreshed every frame rather than on redraw.
+        if opts.overlay {
+            display.draw_overlay(&emu.chip);
+            emu.chip.redraw = false;
+        }
+        else if emu.chip.redraw || display.persistence.animating() {
+            display.draw(&emu.chip.vbuffer);
+            emu.chip.redraw = false;
         }
 
-        thread::sleep(Duration::from_millis(opts.tick_delay));
-        // The rest of the game loop goes here...
+        // Holding tab fast-forwards by skipping frame pacing.
+        let now = Instant::now();
+        if event_pump.keyboard_state().is_scancode_pressed(Scancode::Tab) {
+            next_frame = now + frame_time;
+        }
+        else if next_frame > now {
+            thread::sleep(next_frame - now);
+            next_frame += frame_time;
+        }
+        else {
+            // Running behind, don't try to catch up.
+            next_frame = now + frame_time;
+        }
     }
-    
-    // loop {
-    //     chip.tick();
-    //     chip.display.draw();
-    // }
+    display.palette
 }
 
-pub fn parse_args() -> CHIP8Options {
-    let cli = CLI::parse();
+fn prompt_binding(display: &mut Display, key: u8) {
+    println!("Press key for {key:X}, escape cancels");
+    display.set_title(&format!("CHIP-8 - press key for {key:X}"));
+}
 
-    let quirks = Quirk {
-        vf_reset: cli.vf_reset.unwrap_or(true),
-        mem_inc: cli.mem_inc.unwrap_or(true),
-        display_wait: false,
-        // display_wait: cli.display_wait.unwrap_or(false),
-        clipping: false,
-        // clipping: cli.clipping.unwrap_or(false),
-        shift_x: cli.shift_x.unwrap_or(false),
-        jump_vx: cli.jump_vx.unwrap_or(false),
-    };
+// Command line options take precedence over the config files, see `config::Config`.
+pub fn parse_args(cli: RunArgs, rom: Option<&RomInfo>) -> CHIP8Options {
+    let mut config = Config::load(cli.config.as_deref(), &cli.rom_path, rom).unwrap_or_else(|e| panic!("{e}"));
+
+    let speed = &mut config.speed;
+    speed.ticks_per_frame = cli.ticks_per_frame.unwrap_or(speed.ticks_per_frame);
+    speed.tick_delay = cli.tick_delay.unwrap_or(speed.tick_delay);
+
+    cli.quirks.apply(&mut config.quirks);
+
+    let display = &mut config.display;
+    display.scale = cli.scale.unwrap_or(display.scale);
+    if let Some(palette) = cli.palette {
+        display.palette = palette.to_string();
+    }
+    display.fullscreen = cli.fullscreen.unwrap_or(display.fullscreen);
+    display.integer_scaling = cli.integer_scaling.unwrap_or(display.integer_scaling);
+    display.persistence = cli.persistence.unwrap_or(display.persistence);
+    display.persistence_strength = cli.persistence_strength.or(display.persistence_strength);
+    if !cli.filter.is_empty() {
+        display.filter = cli.filter;
+    }
+
+    let audio = &mut config.audio;
+    audio.mute = cli.mute.unwrap_or(audio.mute);
+    audio.frequency = cli.beep_freq.unwrap_or(audio.frequency);
+    audio.volume = cli.volume.unwrap_or(audio.volume).clamp(0.0, 1.0);
+    audio.waveform = cli.waveform.unwrap_or(audio.waveform);
+
+    if let Some(headless) = cli.headless {
+        config.frontend = if headless { Frontend::Headless } else { Frontend::Window };
+    }
+    config.frames = cli.frames.unwrap_or(config.frames);
+
+    let keymap_file = cli.keymap_file.map(|p| KeymapFile::load(&p).unwrap_or_else(|e| panic!("{e}")));
+    let keymap_files: Vec<&KeymapFile> = [Some(&config.input), keymap_file.as_ref()].into_iter().flatten().collect();
+    let rom_name = config::rom_name(&cli.rom_path);
+    let mut keymap = input::build_keymap(cli.keymap.as_ref(), &keymap_files, &rom_name, &cli.bind)
+        .unwrap_or_else(|e| panic!("{e}"));
+    let mut padmap = gamepad::build_padmap(&keymap_files, &rom_name, &cli.pad_bind, cli.deadzone)
+        .unwrap_or_else(|e| panic!("{e}"));
+    // The database's directions and buttons go on the arrow keys and controller, on top of the bindings.
+    for (name, key) in rom.map(|r| &r.keys).into_iter().flatten() {
+        if let Some(host) = input::rom_key(name) {
+            keymap.add(*key, host);
+        }
+        if let Some(input) = gamepad::rom_input(name) {
+            padmap.add(*key, input);
+        }
+    }
 
-    print!("{quirks:?}");
+    if cli.print_config {
+        print!("{}\n{}\n{}", config.to_toml(), keymap.to_toml("input.keys"), padmap.to_toml("input.gamepad"));
+        std::process::exit(0);
+    }
+
+    let quirks = config.quirks.quirk();
+
+    let display = config.display;
     CHIP8Options {
         rom_path: cli.rom_path.to_str().unwrap().to_string(), 
-        scale: cli.scale.unwrap_or(10), 
-        tick_delay: cli.tick_delay.unwrap_or(2),
-        ticks_per_frame: cli.ticks_per_frame.unwrap_or(30), 
-        debug: cli.debug.unwrap_or(false),
-        quirks: quirks
+        scale: display.scale, 
+        tick_delay: config.speed.tick_delay,
+        ticks_per_frame: config.speed.ticks_per_frame, 
+        audio: AudioSettings {
+            frequency: config.audio.frequency,
+            volume: config.audio.volume,
+            waveform: config.audio.waveform
+        },
+        mute: config.audio.mute,
+        record_audio: cli.record_audio.map(|p| p.to_str().unwrap().to_string()),
+        headless: config.frontend == Frontend::Headless,
+        frames: config.frames,
+        palette: palette::parse_palette(&display.palette).unwrap_or_else(|e| panic!("Invalid palette in config: {e}")),
+        keymap,
+        padmap,
+        fullscreen: display.fullscreen,
+        integer_scaling: display.integer_scaling,
+        persistence: display.persistence,
+        persistence_strength: display.persistence_strength.unwrap_or(
+            if display.persistence == PersistenceMode::Deflicker { 2.0 } else { 0.6 }
+        ),
+        filters: display.filter,
+        record: cli.record.map(|p| p.to_str().unwrap().to_string()),
+        screenshot: cli.screenshot.map(|p| p.to_str().unwrap().to_string()),
+        debug: cli.debug,
+        overlay: cli.overlay,
+        gdb_port: cli.gdb,
+        trace_path: cli.trace.map(|p| p.to_str().unwrap().to_string()),
+        trace_format: cli.trace_format.unwrap_or(TraceFormat::Text),
+        trace_range: cli.trace_range,
+        trace_history: cli.trace_history.unwrap_or(64),
+        quirks
     }
-}
\ No newline at end of file
+}
+
+// Address in decimal or 0x hex, for use as a clap value parser.
+fn parse_addr(s: &str) -> Result<u16, String> {
+    debugger::parse_num(s).filter(|a| *a < 0x1000).ok_or(format!("`{s}` is not an address below 0x1000"))
+}
+
+fn parse_volume(s: &str) -> Result<f32, String> {
+    parse_f32(s).and_then(|v| if (0.0..=1.0).contains(&v) { Ok(v) } else { Err("must be from 0.0 to 1.0".to_string()) })
+}
+
+fn parse_frequency(s: &str) -> Result<f32, String> {
+    parse_f32(s).and_then(|f| if (20.0..=20000.0).contains(&f) { Ok(f) } else { Err("must be from 20 to 20000 Hz".to_string()) })
+}
+
+fn parse_strength(s: &str) -> Result<f32, String> {
+    parse_f32(s).and_then(|v| if v > 0.0 { Ok(v) } else { Err("must be above 0".to_string()) })
+}
+
+fn parse_f32(s: &str) -> Result<f32, String> {
+    s.parse::<f32>().ok().filter(|v| v.is_finite()).ok_or(format!("`{s}` is not a number"))
+}
@@ -0,0 +1,185 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+
+use crate::cpu::CPU;
+use crate::disasm::disassemble_range;
+
+// Glyphs are 3x5 pixels, drawn at FONT_SCALE with a one pixel gap.
+const FONT_SCALE: i32 = 2;
+const CELL_WIDTH: i32 = 4 * FONT_SCALE;
+const CELL_HEIGHT: i32 = 6 * FONT_SCALE;
+const MARGIN: i32 = 8;
+
+const COLUMNS: i32 = 48;
+const ROWS: i32 = 26;
+pub const PANEL_WIDTH: usize = (COLUMNS * CELL_WIDTH + 2 * MARGIN) as usize;
+pub const PANEL_HEIGHT: usize = (ROWS * CELL_HEIGHT + 2 * MARGIN) as usize;
+
+const BACKGROUND: Color = Color::RGB(0x20, 0x20, 0x20);
+const TEXT: Color = Color::RGB(0xC0, 0xC0, 0xC0);
+const TITLE: Color = Color::RGB(0x60, 0xA0, 0xE0);
+const HIGHLIGHT: Color = Color::RGB(0x80, 0x60, 0x00);
+
+// Keypad layout as it appears on the COSMAC VIP.
+const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC,
+    0x4, 0x5, 0x6, 0xD,
+    0x7, 0x8, 0x9, 0xE,
+    0xA, 0x0, 0xB, 0xF
+];
+
+// Draws the debugger panels into a PANEL_WIDTH x PANEL_HEIGHT area at (x, y), in window pixels.
+pub fn draw(canvas: &mut WindowCanvas, x: i32, y: i32, cpu: &CPU) {
+    let mut panel = Panel { canvas, x: x + MARGIN, y: y + MARGIN };
+
+    panel.canvas.set_draw_color(BACKGROUND);
+    let _ = panel.canvas.fill_rect(Rect::new(x, y, PANEL_WIDTH as u32, PANEL_HEIGHT as u32));
+
+    // Left column, disassembly around PC and memory around I.
+    panel.text(0, 0, "DISASSEMBLY", TITLE);
+    let start = cpu.pc().saturating_sub(10);
+    for (row, (addr, op, text)) in disassemble_range(&cpu.memory, start, 11).into_iter().enumerate() {
+        let row = row as i32 + 1;
+        if addr == cpu.pc() {
+            panel.highlight(0, row, 28);
+        }
+        panel.text(0, row, &format!("0x{addr:03x} {op:04X} {text}"), TEXT);
+    }
+
+    panel.text(0, 13, "MEMORY", TITLE);
+    let base = (cpu.i() & !0x7).saturating_sub(16).min(0x1000 - 64);
+    for row in 0..8 {
+        let addr = base + row * 8;
+        panel.text(0, row as i32 + 14, &format!("0x{addr:03x}"), TEXT);
+        for col in 0..8 {
+            let a = addr + col;
+            let cx = 6 + col as i32 * 3;
+            if a == cpu.i() {
+                panel.highlight(cx, row as i32 + 14, 2);
+            }
+            panel.text(cx, row as i32 + 14, &format!("{:02X}", cpu.memory[a as usize]), TEXT);
+        }
+    }
+
+    // Right column, registers, timers, stack and keypad.
+    let col = 31;
+    panel.text(col, 0, "REGISTERS", TITLE);
+    let v = cpu.v();
+    for row in 0..8 {
+        let (a, b) = (row * 2, row * 2 + 1);
+        panel.text(col, row as i32 + 1, &format!("V{a:X}:{:02X} V{b:X}:{:02X}", v[a], v[b]), TEXT);
+    }
+    panel.text(col, 9, &format!("I:0x{:03x}", cpu.i()), TEXT);
+    panel.text(col, 10, &format!("PC:0x{:03x}", cpu.pc()), TEXT);
+    panel.text(col, 11, &format!("DT:{:02X} ST:{:02X}", cpu.dt(), cpu.st()), TEXT);
+
+    panel.text(col, 13, &format!("STACK SP:{:X}", cpu.sp()), TITLE);
+    for (row, addr) in cpu.stack().iter().rev().take(4).enumerate() {
+        panel.text(col, row as i32 + 14, &format!("0x{addr:03x}"), TEXT);
+    }
+
+    panel.text(col, 19, "KEYPAD", TITLE);
+    for (i, key) in KEYPAD.iter().enumerate() {
+        let (cx, cy) = (col + (i as i32 % 4) * 2, 20 + i as i32 / 4);
+        if cpu.keypad.is_pressed(*key) {
+            panel.highlight(cx, cy, 1);
+        }
+        panel.text(cx, cy, &format!("{key:X}"), TEXT);
+    }
+}
+
+struct Panel<'a> {
+    canvas: &'a mut WindowCanvas,
+    x: i32,
+    y: i32
+}
+
+impl Panel<'_> {
+    fn highlight(&mut self, col: i32, row: i32, len: i32) {
+        self.canvas.set_draw_color(HIGHLIGHT);
+        let rect = Rect::new(
+            self.x + col * CELL_WIDTH - FONT_SCALE / 2,
+            self.y + row * CELL_HEIGHT - FONT_SCALE / 2,
+            (len * CELL_WIDTH) as u32,
+            CELL_HEIGHT as u32
+        );
+        let _ = self.canvas.fill_rect(rect);
+    }
+
+    fn text(&mut self, col: i32, row: i32, text: &str, color: Color) {
+        self.canvas.set_draw_color(color);
+        for (i, c) in text.chars().enumerate() {
+            let gx = self.x + (col + i as i32) * CELL_WIDTH;
+            let gy = self.y + row * CELL_HEIGHT;
+            for (py, bits) in glyph(c).iter().enumerate() {
+                for px in 0..3 {
+                    if bits >> (2 - px) & 1 == 1 {
+                        let rect = Rect::new(
+                            gx + px * FONT_SCALE,
+                            gy + py as i32 * FONT_SCALE,
+                            FONT_SCALE as u32,
+                            FONT_SCALE as u32
+                        );
+                        let _ = self.canvas.fill_rect(rect);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn glyph(c: char) -> [u8; 5] {
+    // Lowercase x is kept distinct so hex prefixes stay readable.
+    if c == 'x' {
+        return [0b000, 0b101, 0b010, 0b101, 0b000];
+    }
+    match c.to_ascii_uppercase() {
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010]
+    }
+}
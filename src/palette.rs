@@ -0,0 +1,94 @@
+use std::fmt;
+
+use sdl2::pixels::Color;
+
+// Colours indexed by pixel value: 0 background, 1 foreground.
+// 2 and 3 are for two-plane modes (second plane only, and both planes).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub colors: [Color; 4]
+}
+
+impl Palette {
+    const fn rgb(colors: [u32; 4]) -> Self {
+        Palette { colors: [hex(colors[0]), hex(colors[1]), hex(colors[2]), hex(colors[3])] }
+    }
+
+    pub fn background(&self) -> Color {
+        self.colors[0]
+    }
+
+    pub fn foreground(&self) -> Color {
+        self.colors[1]
+    }
+
+    // RGB triples for image encoders.
+    pub fn to_rgb(&self, count: usize) -> Vec<[u8; 3]> {
+        self.colors[..count].iter().map(|c| [c.r, c.g, c.b]).collect()
+    }
+}
+
+// Theme name, or the colours as taken by `parse_palette`.
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((name, _)) = THEMES.iter().find(|(_, p)| p == self) {
+            return write!(f, "{name}");
+        }
+        let colors: Vec<String> = self.colors.iter().map(|c| format!("#{:02x}{:02x}{:02x}", c.r, c.g, c.b)).collect();
+        write!(f, "{}", colors.join(","))
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        THEMES[0].1
+    }
+}
+
+pub const THEMES: [(&str, Palette); 7] = [
+    ("classic", Palette::rgb([0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555])),
+    ("octo", Palette::rgb([0x996600, 0xFFCC00, 0xFF6600, 0x662200])),
+    ("lcd", Palette::rgb([0xF9FFB3, 0x3D8026, 0xABCC47, 0x00131A])),
+    ("amber", Palette::rgb([0x1A0D00, 0xFFB000, 0x996A00, 0xFFD480])),
+    ("green", Palette::rgb([0x001100, 0x33FF33, 0x1A801A, 0xB3FFB3])),
+    ("hotdog", Palette::rgb([0x000000, 0xFF0000, 0xFFFF00, 0xFFFFFF])),
+    ("cga", Palette::rgb([0x000000, 0x55FFFF, 0xFF55FF, 0xFFFFFF]))
+];
+
+pub fn theme(name: &str) -> Option<Palette> {
+    THEMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, p)| *p)
+}
+
+// Theme after `palette` in THEMES, wrapping around, for cycling at runtime.
+pub fn next_theme(palette: &Palette) -> (&'static str, Palette) {
+    let i = THEMES.iter().position(|(_, p)| p == palette).map_or(0, |i| (i + 1) % THEMES.len());
+    THEMES[i]
+}
+
+// Parses a theme name or comma separated hex colours, e.g. `amber` or `#000000,#ffffff`.
+// Unspecified colours are taken from the classic theme. For use as a clap value parser.
+pub fn parse_palette(s: &str) -> Result<Palette, String> {
+    if let Some(palette) = theme(s) {
+        return Ok(palette);
+    }
+
+    let mut palette = Palette::default();
+    let colors: Vec<&str> = s.split(',').collect();
+    if colors.len() > 4 {
+        return Err("at most 4 colours can be given".to_string());
+    }
+    for (i, color) in colors.iter().enumerate() {
+        let digits = color.trim().trim_start_matches('#');
+        let rgb = u32::from_str_radix(digits, 16).ok().filter(|_| digits.len() == 6);
+        let Some(rgb) = rgb else {
+            let names: Vec<&str> = THEMES.iter().map(|(n, _)| *n).collect();
+            return Err(format!("`{color}` is not a hex colour or one of the themes: {}", names.join(", ")));
+        };
+        palette.colors[i] = hex(rgb);
+    }
+    Ok(palette)
+}
+
+const fn hex(rgb: u32) -> Color {
+    Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+}
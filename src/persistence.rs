@@ -0,0 +1,64 @@
+use std::collections::VecDeque;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::display::DISPLAY_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PersistenceMode {
+    /// Present the framebuffer as is.
+    Off,
+    /// Lit pixels fade out over several frames, strength is the fraction of brightness kept each frame (0.0 to 1.0).
+    Phosphor,
+    /// A pixel is lit if it was lit in any of the last N frames, strength is N.
+    Deflicker
+}
+
+// Post-processes the framebuffer once per presented frame to hide XOR sprite flicker.
+pub struct Persistence {
+    mode: PersistenceMode,
+    strength: f32,
+    brightness: [f32; DISPLAY_SIZE],
+    history: VecDeque<[bool; DISPLAY_SIZE]>
+}
+
+impl Persistence {
+    pub fn new(mode: PersistenceMode, strength: f32) -> Self {
+        Persistence { mode, strength, brightness: [0.0; DISPLAY_SIZE], history: VecDeque::new() }
+    }
+
+    // Whether the output changes from frame to frame even when the framebuffer doesn't.
+    pub fn animating(&self) -> bool {
+        self.mode != PersistenceMode::Off
+    }
+
+    // Returns the brightness of each pixel from 0.0 (background) to 1.0 (foreground).
+    pub fn apply(&mut self, pixels: &[bool; DISPLAY_SIZE]) -> &[f32; DISPLAY_SIZE] {
+        match self.mode {
+            PersistenceMode::Off => {
+                for (b, p) in self.brightness.iter_mut().zip(pixels) {
+                    *b = *p as u8 as f32;
+                }
+            }
+            PersistenceMode::Phosphor => {
+                let decay = self.strength.clamp(0.0, 1.0);
+                for (b, p) in self.brightness.iter_mut().zip(pixels) {
+                    *b = if *p { 1.0 } else { *b * decay };
+                }
+            }
+            PersistenceMode::Deflicker => {
+                let frames = (self.strength.round() as usize).max(1);
+                self.history.push_back(*pixels);
+                while self.history.len() > frames {
+                    self.history.pop_front();
+                }
+                for (i, b) in self.brightness.iter_mut().enumerate() {
+                    *b = self.history.iter().any(|f| f[i]) as u8 as f32;
+                }
+            }
+        }
+        &self.brightness
+    }
+}
@@ -1,9 +1,70 @@
+use clap::Args;
+
+use crate::config::QuirkConfig;
+
 #[derive(Clone, Copy, Debug)]
 pub struct Quirk {
     pub vf_reset: bool,
     pub mem_inc: bool,
+    pub mem_inc_x: bool,
     pub display_wait: bool,
     pub clipping: bool,
     pub shift_x: bool,
-    pub jump_vx: bool
-}
\ No newline at end of file
+    pub jump_vx: bool,
+    pub key_release: bool,
+    pub i_overflow: bool
+}
+
+// Quirk switches shared by the subcommands that run a ROM. Each takes an optional value,
+// so `--shift-x` turns a quirk on and `--vf-reset=false` turns off one enabled by default.
+#[derive(Args, Clone, Copy, Debug, Default)]
+pub struct QuirkArgs {
+    /// Opcodes [0x8XY1-3] will reset VF, (default true).
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub vf_reset: Option<bool>,
+
+    /// Opcodes [0xFX55] and [0xFX65] will increment I, (default true).
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub mem_inc: Option<bool>,
+
+    /// With mem-inc, I is incremented by X instead of X + 1, like SUPER-CHIP 1.0.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub mem_inc_x: Option<bool>,
+
+    // Idk not implemented
+    // pub display_wait: Option<bool>,
+
+    /// Clip sprites instead of wrapping when they go off screen.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub clipping: Option<bool>,
+
+    /// Shift use Vx for shifting instead of Vy in [0x8XY6] and [0x8XYE].
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub shift_x: Option<bool>,
+
+    /// Opcode [0xBNNN] will jump to NNN + VX, where X is the highest nibble of NNN, instead of NNN + V0.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub jump_vx: Option<bool>,
+
+    /// FX0A waits for the key to be released like the VIP, rather than returning on the press, (default true).
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub key_release: Option<bool>,
+
+    /// Opcode [0xFX1E] sets VF when I goes past 0xFFF and clears it otherwise, like the Amiga interpreter.
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+    pub i_overflow: Option<bool>
+}
+
+impl QuirkArgs {
+    // Quirks given on the command line replace those in `config`.
+    pub fn apply(&self, config: &mut QuirkConfig) {
+        config.vf_reset = self.vf_reset.unwrap_or(config.vf_reset);
+        config.mem_inc = self.mem_inc.unwrap_or(config.mem_inc);
+        config.mem_inc_x = self.mem_inc_x.unwrap_or(config.mem_inc_x);
+        config.clipping = self.clipping.unwrap_or(config.clipping);
+        config.shift_x = self.shift_x.unwrap_or(config.shift_x);
+        config.jump_vx = self.jump_vx.unwrap_or(config.jump_vx);
+        config.key_release = self.key_release.unwrap_or(config.key_release);
+        config.i_overflow = self.i_overflow.unwrap_or(config.i_overflow);
+    }
+}
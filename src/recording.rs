@@ -0,0 +1,111 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use gif::{Encoder, Frame, Repeat};
+
+use crate::display::{scale_pixels, DISPLAY_HEIGHT, DISPLAY_SIZE, DISPLAY_WIDTH};
+use crate::palette::Palette;
+
+// Records the screen to an animated GIF, one call to `frame` per 60Hz frame.
+// Identical consecutive frames are merged into one with a longer delay.
+pub struct GifRecorder {
+    encoder: Encoder<BufWriter<File>>,
+    scale: usize,
+    // Palette written to the file header, frames using any other palette carry their own.
+    global_palette: Palette,
+    palette: Palette,
+    pending: Option<([bool; DISPLAY_SIZE], Palette)>,
+    // How many 60Hz frames the pending frame has been shown for.
+    pending_frames: u64,
+    // 60Hz frames written so far, delays are derived from this so rounding doesn't drift.
+    elapsed: u64
+}
+
+impl GifRecorder {
+    pub fn create(path: &Path, scale: usize, palette: Palette) -> io::Result<Self> {
+        let global: Vec<u8> = palette.to_rgb(2).concat();
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = Encoder::new(file, (DISPLAY_WIDTH * scale) as u16, (DISPLAY_HEIGHT * scale) as u16, &global)
+            .map_err(io::Error::other)?;
+        encoder.set_repeat(Repeat::Infinite).map_err(io::Error::other)?;
+
+        Ok(GifRecorder {
+            encoder, scale, global_palette: palette, palette, pending: None, pending_frames: 0, elapsed: 0
+        })
+    }
+
+    // Applies to frames recorded from now on.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn frame(&mut self, pixels: &[bool; DISPLAY_SIZE]) -> io::Result<()> {
+        if self.pending.as_ref() == Some(&(*pixels, self.palette)) {
+            self.pending_frames += 1;
+            return Ok(());
+        }
+        self.flush()?;
+        self.pending = Some((*pixels, self.palette));
+        self.pending_frames = 1;
+        Ok(())
+    }
+
+    // Writes the last frame, the file is complete once the recorder is dropped.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let Some((pixels, palette)) = self.pending.take() else { return Ok(()) };
+
+        // GIF delays are in hundredths of a second.
+        let start = self.elapsed * 100 / 60;
+        self.elapsed += self.pending_frames;
+        let end = self.elapsed * 100 / 60;
+
+        let (width, height) = (DISPLAY_WIDTH * self.scale, DISPLAY_HEIGHT * self.scale);
+        let indices = scale_pixels(&pixels, self.scale);
+        let local = (palette != self.global_palette).then(|| palette.to_rgb(2).concat());
+        let mut frame = Frame::from_indexed_pixels(width as u16, height as u16, indices, None);
+        frame.palette = local;
+        // A screen held longer than a GIF delay can hold, about 11 minutes, is written several times.
+        let mut delay = end - start;
+        loop {
+            frame.delay = u16::try_from(delay).unwrap_or(u16::MAX);
+            self.encoder.write_frame(&frame).map_err(io::Error::other)?;
+            delay -= frame.delay as u64;
+            if delay == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_still_frames_keep_their_delay() {
+        let path = std::env::temp_dir().join(format!("chip8-recording-{}.gif", std::process::id()));
+        let mut recorder = GifRecorder::create(&path, 1, Palette::default()).unwrap();
+        // 40000 frames at 60Hz are 66666 hundredths of a second, more than one delay holds.
+        for _ in 0..40000 {
+            recorder.frame(&[false; DISPLAY_SIZE]).unwrap();
+        }
+        recorder.frame(&[true; DISPLAY_SIZE]).unwrap();
+        recorder.finish().unwrap();
+        drop(recorder);
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delays.push(frame.delay);
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(delays, [u16::MAX, 1131, 2]);
+    }
+}
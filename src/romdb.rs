@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::config;
+
+// ROM database in the format of the community CHIP-8 database: programs.json, a list of programs
+// with their ROMs keyed by SHA-1, sha1-hashes.json mapping each hash to its program's index, and
+// platforms.json with the quirks of each platform. Files in the user's database directory are
+// layered over the bundled ones, which only know the test ROMs in tests/roms, so the full
+// community database can be dropped in there.
+const BUNDLED_PROGRAMS: &str = include_str!("../db/programs.json");
+const BUNDLED_HASHES: &str = include_str!("../db/sha1-hashes.json");
+const BUNDLED_PLATFORMS: &str = include_str!("../db/platforms.json");
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Program {
+    pub title: String,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub roms: HashMap<String, RomEntry>
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RomEntry {
+    #[serde(default)]
+    pub platforms: Vec<String>,
+    // Quirks differing from the platform's for ROMs that need them.
+    #[serde(default)]
+    pub quirky_platforms: HashMap<String, Quirks>,
+    pub tickrate: Option<u32>,
+    pub colors: Option<Colors>,
+    // CHIP-8 key for each direction and action button, e.g. `"up": 5`.
+    #[serde(default)]
+    pub keys: HashMap<String, u8>
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Colors {
+    #[serde(default)]
+    pub pixels: Vec<String>
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Quirks {
+    pub shift: Option<bool>,
+    pub memory_increment_by_x: Option<bool>,
+    pub memory_leave_i_unchanged: Option<bool>,
+    pub wrap: Option<bool>,
+    pub jump: Option<bool>,
+    pub vblank: Option<bool>,
+    pub logic: Option<bool>
+}
+
+impl Quirks {
+    // Quirks set in `other` replace those in self.
+    fn overlay(self, other: Quirks) -> Quirks {
+        Quirks {
+            shift: other.shift.or(self.shift),
+            memory_increment_by_x: other.memory_increment_by_x.or(self.memory_increment_by_x),
+            memory_leave_i_unchanged: other.memory_leave_i_unchanged.or(self.memory_leave_i_unchanged),
+            wrap: other.wrap.or(self.wrap),
+            jump: other.jump.or(self.jump),
+            vblank: other.vblank.or(self.vblank),
+            logic: other.logic.or(self.logic)
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Platform {
+    pub id: String,
+    pub name: String,
+    pub default_tickrate: Option<u32>,
+    #[serde(default)]
+    pub quirks: Quirks
+}
+
+// Everything known about a ROM, with the platform defaults already applied.
+#[derive(Clone, Debug)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub platform: Option<Platform>,
+    pub quirks: Quirks,
+    pub tickrate: Option<u32>,
+    pub colors: Vec<String>,
+    pub keys: HashMap<String, u8>
+}
+
+pub struct RomDatabase {
+    programs: Vec<Program>,
+    hashes: HashMap<String, usize>,
+    platforms: Vec<Platform>
+}
+
+impl RomDatabase {
+    // The bundled database extended by the files in `dir`, if it exists.
+    pub fn load(dir: Option<&Path>) -> Result<Self, String> {
+        let mut db = RomDatabase {
+            programs: parse(BUNDLED_PROGRAMS, "bundled programs.json")?,
+            hashes: parse(BUNDLED_HASHES, "bundled sha1-hashes.json")?,
+            platforms: parse(BUNDLED_PLATFORMS, "bundled platforms.json")?
+        };
+
+        let Some(dir) = dir else { return Ok(db) };
+        if let Some(programs) = read::<Vec<Program>>(&dir.join("programs.json"))? {
+            // User hashes index into the user's programs, which go after the bundled ones.
+            let offset = db.programs.len();
+            db.programs.extend(programs);
+            let hashes: HashMap<String, usize> = read(&dir.join("sha1-hashes.json"))?.unwrap_or_default();
+            for (hash, index) in hashes {
+                db.hashes.insert(hash.to_lowercase(), index + offset);
+            }
+        }
+        for platform in read::<Vec<Platform>>(&dir.join("platforms.json"))?.unwrap_or_default() {
+            db.platforms.retain(|p| p.id != platform.id);
+            db.platforms.push(platform);
+        }
+        Ok(db)
+    }
+
+    pub fn platform(&self, id: &str) -> Option<&Platform> {
+        self.platforms.iter().find(|p| p.id == id)
+    }
+
+    pub fn lookup(&self, sha1: &str) -> Option<RomInfo> {
+        let program = self.programs.get(*self.hashes.get(sha1)?)?;
+        let rom = program.roms.iter().find(|(hash, _)| hash.eq_ignore_ascii_case(sha1)).map(|(_, r)| r.clone()).unwrap_or_default();
+
+        // The first platform listed is the one the ROM was made for.
+        let platform = rom.platforms.first().and_then(|id| self.platform(id)).cloned();
+        let mut quirks = platform.as_ref().map(|p| p.quirks).unwrap_or_default();
+        if let Some(overrides) = platform.as_ref().and_then(|p| rom.quirky_platforms.get(&p.id)) {
+            quirks = quirks.overlay(*overrides);
+        }
+
+        Some(RomInfo {
+            title: program.title.clone(),
+            authors: program.authors.clone(),
+            tickrate: rom.tickrate.or(platform.as_ref().and_then(|p| p.default_tickrate)),
+            platform,
+            quirks,
+            colors: rom.colors.map(|c| c.pixels).unwrap_or_default(),
+            keys: rom.keys
+        })
+    }
+}
+
+// Where users can put their own database files.
+pub fn user_dir() -> Option<PathBuf> {
+    config::dir().map(|d| d.join("database"))
+}
+
+pub fn sha1(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+fn parse<T: DeserializeOwned>(json: &str, name: &str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|e| format!("Invalid {name}: {e}"))
+}
+
+// Missing files are skipped, so a user database can consist of only some of them.
+fn read<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, String> {
+    match fs::read_to_string(path) {
+        Ok(json) => parse(&json, &path.display().to_string()).map(Some),
+        Err(_) => Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    #[test]
+    fn bundled_hashes_point_at_their_programs() {
+        let db = RomDatabase::load(None).unwrap();
+        for (hash, index) in &db.hashes {
+            let program = &db.programs[*index];
+            assert!(program.roms.contains_key(hash), "{hash} points at {}, which doesn't list it", program.title);
+        }
+    }
+
+    #[test]
+    fn fixture_roms_are_bundled() {
+        let db = RomDatabase::load(None).unwrap();
+        for (name, title) in [("font", "Font test"), ("flags", "Flags test"), ("quirks", "Quirks test"), ("keypad", "Keypad test"), ("beep", "Beep test")] {
+            let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms").join(name).with_extension("s");
+            let rom = asm::assemble(&fs::read_to_string(path).unwrap()).unwrap();
+            let info = db.lookup(&sha1(&rom)).unwrap_or_else(|| panic!("{name}.s changed, update its hash in db/"));
+            assert_eq!(info.title, title);
+            assert_eq!(info.platform.map(|p| p.id).as_deref(), Some("originalChip8"));
+        }
+    }
+}
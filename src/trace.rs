@@ -0,0 +1,177 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::cpu::CPU;
+use crate::debugger::parse_num;
+use crate::disasm::disassemble;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum TraceFormat {
+    /// One line per instruction: cycle, PC, opcode, disassembly, changed registers and memory writes.
+    Text,
+    /// Little-endian records: cycle (u64), PC (u16), opcode (u16), change count (u8) followed by
+    /// (register u8, value u16) pairs, then write count (u8) followed by (address u16, value u8) pairs.
+    /// Registers are numbered 0-15 for V0-VF, then 16 I, 17 SP, 18 DT, 19 ST.
+    Binary
+}
+
+// Register state captured before an instruction executes.
+#[derive(Clone, Copy, Debug)]
+pub struct Snapshot {
+    pc: u16,
+    op: u16,
+    regs: [u16; 20]
+}
+
+impl Snapshot {
+    pub fn capture(cpu: &CPU) -> Self {
+        let mut regs = [0; 20];
+        for (r, v) in cpu.v().iter().enumerate() {
+            regs[r] = *v as u16;
+        }
+        regs[16] = cpu.i();
+        regs[17] = cpu.sp() as u16;
+        regs[18] = cpu.dt() as u16;
+        regs[19] = cpu.st() as u16;
+
+        Snapshot { pc: cpu.pc(), op: cpu.next_op(), regs }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub pc: u16,
+    pub op: u16,
+    // Registers written with a new value, as (register, value).
+    pub changes: Vec<(u8, u16)>,
+    pub writes: Vec<(u16, u8)>
+}
+
+impl TraceEntry {
+    fn to_text(&self) -> String {
+        let mut line = format!("{:>10} {:03x} {:04x}  {:<18}", self.cycle, self.pc, self.op, disassemble(self.op));
+        for (r, val) in &self.changes {
+            line += &format!(" {}={val:x}", reg_name(*r));
+        }
+        for (addr, val) in &self.writes {
+            line += &format!(" [{addr:03x}]={val:02x}");
+        }
+        line
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16);
+        bytes.extend_from_slice(&self.cycle.to_le_bytes());
+        bytes.extend_from_slice(&self.pc.to_le_bytes());
+        bytes.extend_from_slice(&self.op.to_le_bytes());
+        bytes.push(self.changes.len() as u8);
+        for (r, val) in &self.changes {
+            bytes.push(*r);
+            bytes.extend_from_slice(&val.to_le_bytes());
+        }
+        bytes.push(self.writes.len() as u8);
+        for (addr, val) in &self.writes {
+            bytes.extend_from_slice(&addr.to_le_bytes());
+            bytes.push(*val);
+        }
+        bytes
+    }
+}
+
+pub struct Tracer {
+    out: Option<BufWriter<File>>,
+    format: TraceFormat,
+    // Inclusive range of PCs written to the log.
+    range: Option<(u16, u16)>,
+    // The last `capacity` instructions regardless of range, dumped on faults.
+    history: VecDeque<TraceEntry>,
+    capacity: usize,
+    cycle: u64
+}
+
+impl Tracer {
+    pub fn new(path: Option<&Path>, format: TraceFormat, range: Option<(u16, u16)>, capacity: usize) -> io::Result<Self> {
+        let out = match path {
+            Some(path) => Some(BufWriter::new(File::create(path)?)),
+            None => None
+        };
+
+        Ok(Tracer {
+            out, format, range, history: VecDeque::with_capacity(capacity), capacity, cycle: 0
+        })
+    }
+
+    // Records the instruction executed since `before` was captured.
+    pub fn record(&mut self, before: Snapshot, cpu: &CPU) {
+        let after = Snapshot::capture(cpu);
+        let changes = (0..before.regs.len())
+            .filter(|r| before.regs[*r] != after.regs[*r])
+            .map(|r| (r as u8, after.regs[r]))
+            .collect();
+
+        let entry = TraceEntry {
+            cycle: self.cycle,
+            pc: before.pc,
+            op: before.op,
+            changes,
+            writes: cpu.last_writes().to_vec()
+        };
+        self.cycle += 1;
+
+        if self.range.is_none_or(|(start, end)| (start..=end).contains(&entry.pc)) {
+            if let Some(out) = self.out.as_mut() {
+                let result = match self.format {
+                    TraceFormat::Text => writeln!(out, "{}", entry.to_text()),
+                    TraceFormat::Binary => out.write_all(&entry.to_bytes())
+                };
+                if let Err(e) = result {
+                    println!("Failed to write trace, disabling: {e}");
+                    self.out = None;
+                }
+            }
+        }
+
+        if self.capacity > 0 {
+            if self.history.len() == self.capacity {
+                self.history.pop_front();
+            }
+            self.history.push_back(entry);
+        }
+    }
+
+    pub fn dump_history(&self) {
+        println!("Last {} instructions:", self.history.len());
+        for entry in &self.history {
+            println!("{}", entry.to_text());
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(out) = self.out.as_mut() {
+            let _ = out.flush();
+        }
+    }
+}
+
+fn reg_name(r: u8) -> String {
+    match r {
+        0..=15 => format!("V{r:X}"),
+        16 => "I".to_string(),
+        17 => "SP".to_string(),
+        18 => "DT".to_string(),
+        _ => "ST".to_string()
+    }
+}
+
+// Parses an inclusive address range such as `0x200-0x2ff`, for use as a clap value parser.
+pub fn parse_range(s: &str) -> Result<(u16, u16), String> {
+    let (start, end) = s.split_once('-').ok_or("expected <start>-<end>")?;
+    let start = parse_num(start).ok_or(format!("invalid address `{start}`"))?;
+    let end = parse_num(end).ok_or(format!("invalid address `{end}`"))?;
+    Ok((start, end))
+}
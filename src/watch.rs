@@ -0,0 +1,77 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite
+}
+
+impl Access {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "r" => Some(Access::Read),
+            "w" => Some(Access::Write),
+            "rw" => Some(Access::ReadWrite),
+            _ => None
+        }
+    }
+
+    fn matches(&self, write: bool) -> bool {
+        match self {
+            Access::Read => !write,
+            Access::Write => write,
+            Access::ReadWrite => true
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+    // Inclusive range of memory addresses.
+    Memory { start: u16, end: u16 },
+    I,
+    V(u8)
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Memory { start, end } if start == end => write!(f, "RAM[0x{start:03x}]"),
+            Target::Memory { start, end } => write!(f, "RAM[0x{start:03x}..=0x{end:03x}]"),
+            Target::I => write!(f, "I"),
+            Target::V(x) => write!(f, "V{x:X}")
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Watchpoint {
+    pub target: Target,
+    pub access: Access
+}
+
+impl Watchpoint {
+    pub fn matches(&self, target: Target, write: bool) -> bool {
+        if !self.access.matches(write) {
+            return false;
+        }
+        match (self.target, target) {
+            (Target::Memory { start, end }, Target::Memory { start: addr, .. }) => (start..=end).contains(&addr),
+            (a, b) => a == b
+        }
+    }
+}
+
+// Recorded by the CPU when an access matches a watchpoint.
+// `target` is the exact location accessed, for memory a single address.
+#[derive(Clone, Copy, Debug)]
+pub struct WatchHit {
+    pub index: usize,
+    pub pc: u16,
+    pub op: u16,
+    pub target: Target,
+    pub write: bool,
+    pub old: u16,
+    pub new: u16
+}
@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// Writes mono 16-bit PCM WAV files, sizes in the header are filled in by `finish`.
+pub struct WavWriter {
+    out: BufWriter<File>,
+    samples: u32
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        out.write_all(&1u16.to_le_bytes())?;                 // PCM
+        out.write_all(&1u16.to_le_bytes())?;                 // Channels
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * 2).to_le_bytes())?;    // Byte rate
+        out.write_all(&2u16.to_le_bytes())?;                 // Block align
+        out.write_all(&16u16.to_le_bytes())?;                // Bits per sample
+
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter { out, samples: 0 })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        for s in samples {
+            let s = (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.out.write_all(&s.to_le_bytes())?;
+        }
+        self.samples += samples.len() as u32;
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        let data_size = self.samples * 2;
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(36 + data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&data_size.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()
+    }
+}
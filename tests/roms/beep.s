@@ -0,0 +1,8 @@
+; Shows a B and sets the sound timer while B is held.
+        LD V0, 0xb
+        LD V1, 4
+        LD F, V0
+        DRW V1, V1, 5
+loop:   SKNP V0
+        LD ST, V1
+        JP loop
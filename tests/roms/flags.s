@@ -0,0 +1,69 @@
+; Shows VF after arithmetic and shift instructions as a row of digits, left to right:
+;   1  8XY4 with a carry
+;   0  8XY4 without one
+;   1  8XY5 without a borrow
+;   0  8XY5 with a borrow
+;   1  8XY7 without a borrow
+;   1  8XY5 with equal operands, no borrow
+;   1  8XY6 shifting out a 1
+;   1  8XYE shifting out a 1
+; and on the second row the same with VF as the destination, where the flag replaces the result:
+;   1  8FY4 with a carry
+;   1  8FY5 without a borrow
+;   0  8FY7 with a borrow
+;   1  8FFE shifting out a 1
+        LD VA, 1
+        LD VB, 2
+
+        LD V1, 0xff
+        LD V2, 0x01
+        ADD V1, V2
+        CALL show
+        LD V1, 0x10
+        ADD V1, V2
+        CALL show
+        LD V1, 0x05
+        LD V2, 0x03
+        SUB V1, V2
+        CALL show
+        LD V1, 0x03
+        LD V2, 0x05
+        SUB V1, V2
+        CALL show
+        LD V1, 0x03
+        SUBN V1, V2
+        CALL show
+        LD V1, 0x05
+        SUB V1, V2
+        CALL show
+        LD V1, 0x03
+        SHR V1, V1
+        CALL show
+        LD V1, 0x80
+        SHL V1, V1
+        CALL show
+
+        LD VA, 1
+        LD VB, 10
+        LD VF, 0xff
+        LD V2, 0x01
+        ADD VF, V2
+        CALL show
+        LD VF, 0x05
+        LD V2, 0x03
+        SUB VF, V2
+        CALL show
+        LD VF, 0x05
+        SUBN VF, V2
+        CALL show
+        LD VF, 0x81
+        SHL VF, VF
+        CALL show
+done:   JP done
+
+; Draws the digit for VF at VA, VB and moves VA along.
+show:   LD V3, VF
+        LD F, V3
+        DRW VA, VB, 5
+        ADD VA, 8
+        RET
@@ -0,0 +1,16 @@
+; Draws the 16 built-in font digits in two rows, 0-7 above 8-F.
+        LD V0, 0        ; digit
+        LD VA, 4        ; x
+        LD VB, 4        ; y
+next:   LD F, V0
+        DRW VA, VB, 5
+        ADD VA, 7
+        ADD V0, 1
+        SE V0, 8
+        JP same_row
+        LD VA, 4
+        LD VB, 12
+same_row:
+        SE V0, 16
+        JP next
+done:   JP done
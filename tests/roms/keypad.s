@@ -0,0 +1,8 @@
+; Waits for a key with FX0A and shows it, over and over along a row.
+        LD VA, 1
+        LD VB, 2
+next:   LD V0, K
+        LD F, V0
+        DRW VA, VB, 5
+        ADD VA, 8
+        JP next
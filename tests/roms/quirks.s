@@ -0,0 +1,70 @@
+; Shows how the quirk-sensitive instructions behaved as a row of digits, left to right, with the
+; digit when the quirk is off and when it is on:
+;   5 0  vf_reset, VF after 8XY1
+;   0 1  mem_inc, byte read by a second FX65 from the same I (0 with mem_inc_x too)
+;   2 0  shift_x, 8XY6 with VX 1 and VY 4
+;   0 1  jump_vx, which of two jumps BNNN took with V0 0 and V2 2
+;   1 0  clipping, whether a sprite drawn off the right edge wrapped around
+;   0 1  i_overflow, VF after FX1E going past 0xFFF
+        LD VA, 1
+        LD VB, 2
+
+        LD VF, 5
+        LD V1, 3
+        LD V2, 4
+        OR V1, V2
+        CALL show
+
+        LD I, table
+        LD V0, [I]
+        LD V0, [I]
+        LD VF, V0
+        CALL show
+
+        LD V1, 1
+        LD V2, 4
+        SHR V1, V2
+        LD VF, V1
+        CALL show
+
+        LD V0, 0
+        LD V2, 2
+        JP V0, jumps
+jumps:  JP jump0
+        JP jump1
+jump0:  LD VF, 0
+        JP jumped
+jump1:  LD VF, 1
+jumped: CALL show
+
+        LD I, row
+        LD V1, 60
+        LD V2, 28
+        DRW V1, V2, 1
+        LD V4, 0
+        LD I, dot
+        DRW V4, V2, 1
+        LD V5, VF
+        DRW V4, V2, 1
+        LD I, row
+        DRW V1, V2, 1
+        LD VF, V5
+        CALL show
+
+        LD VF, 0
+        LD I, 0xfff
+        LD V1, 2
+        ADD I, V1
+        CALL show
+done:   JP done
+
+; Draws the digit for VF at VA, VB and moves VA along.
+show:   LD V3, VF
+        LD F, V3
+        DRW VA, VB, 5
+        ADD VA, 8
+        RET
+
+table:  DB 0, 1
+row:    DB 0xff
+dot:    DB 0x80